@@ -0,0 +1,212 @@
+use anyhow::{bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde_yaml::{Mapping, Value};
+use std::env;
+use std::fs::File;
+use std::path::Path;
+
+/// 分层合并后的配置
+/// 以默认值为底，依次合并配置文件与 `{env_prefix}SECTION__KEY` 形式的环境变量，
+/// 各服务再按 `server.port` 形式的键逐项读取，出错时错误信息会指明配置项
+#[derive(Debug, Clone)]
+pub struct LayeredConfig {
+    value: Value,
+    env_prefix: &'static str,
+}
+
+impl LayeredConfig {
+    /// 加载配置
+    /// 按 默认值 < `files` 中第一个存在的文件 < 环境变量 `env_key` 指定的文件 <
+    /// `{env_prefix}SECTION__KEY` 环境变量 的优先级逐层合并
+    ///
+    /// # 参数
+    /// * `defaults` - 默认配置
+    /// * `files` - 候选配置文件路径
+    /// * `env_key` - 指定额外配置文件路径的环境变量名
+    /// * `env_prefix` - 覆盖配置项的环境变量前缀，如 `CHAT__`
+    ///
+    /// # 返回
+    /// * `Result<Self>` - 成功则返回合并后的配置，文件无法读取或解析时返回错误
+    pub fn load(
+        defaults: Value,
+        files: &[&str],
+        env_key: &str,
+        env_prefix: &'static str,
+    ) -> Result<Self> {
+        let mut paths: Vec<String> = files
+            .iter()
+            .find(|path| Path::new(path).exists())
+            .map(|path| path.to_string())
+            .into_iter()
+            .collect();
+        if let Ok(path) = env::var(env_key) {
+            paths.push(path);
+        }
+
+        let mut layers = Vec::with_capacity(paths.len());
+        for path in paths {
+            let reader = File::open(&path)
+                .with_context(|| format!("failed to open config file {}", path))?;
+            let layer: Value = serde_yaml::from_reader(reader)
+                .with_context(|| format!("failed to parse config file {}", path))?;
+            layers.push(layer);
+        }
+        Ok(Self::from_layers(defaults, layers, env::vars(), env_prefix))
+    }
+
+    /// 合并配置
+    ///
+    /// # 参数
+    /// * `defaults` - 默认配置
+    /// * `layers` - 按优先级从低到高排列的配置文件内容
+    /// * `vars` - 环境变量，只有以 `env_prefix` 开头的会生效
+    /// * `env_prefix` - 覆盖配置项的环境变量前缀
+    ///
+    /// # 返回
+    /// * `Self` - 合并后的配置
+    pub fn from_layers(
+        defaults: Value,
+        layers: impl IntoIterator<Item = Value>,
+        vars: impl IntoIterator<Item = (String, String)>,
+        env_prefix: &'static str,
+    ) -> Self {
+        let mut value = defaults;
+        for layer in layers {
+            merge(&mut value, layer);
+        }
+        for (key, var) in vars {
+            if let Some(path) = key.strip_prefix(env_prefix) {
+                let path: Vec<String> = path.split("__").map(|s| s.to_lowercase()).collect();
+                set_path(&mut value, &path, parse_env_value(&var));
+            }
+        }
+        Self { value, env_prefix }
+    }
+
+    /// 读取 `server.port` 形式的配置项，缺失或类型不符时返回指明该配置项的错误
+    pub fn field<T: DeserializeOwned>(&self, key: &str) -> Result<T> {
+        match self.optional_field(key)? {
+            Some(value) => Ok(value),
+            None => bail!(
+                "missing config key `{}` (set it in the config file or via {}{})",
+                key,
+                self.env_prefix,
+                key.replace('.', "__").to_uppercase()
+            ),
+        }
+    }
+
+    /// 读取可选的配置项，缺失时返回 None，类型不符时返回指明该配置项的错误
+    pub fn optional_field<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        let value = key
+            .split('.')
+            .try_fold(&self.value, |node, key| node.get(key))
+            .filter(|v| !v.is_null());
+        value
+            .map(|value| serde_yaml::from_value(value.clone()))
+            .transpose()
+            .with_context(|| format!("invalid config key `{}`", key))
+    }
+}
+
+/// 递归合并两个 YAML 值，映射按键合并，其余类型由 `other` 覆盖
+fn merge(base: &mut Value, other: Value) {
+    match (base, other) {
+        (Value::Mapping(base), Value::Mapping(other)) => {
+            for (key, value) in other {
+                match base.get_mut(&key) {
+                    Some(slot) => merge(slot, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, other) => *base = other,
+    }
+}
+
+fn set_path(root: &mut Value, path: &[String], value: Value) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let mut node = root;
+    for key in parents {
+        if !node.is_mapping() {
+            *node = Value::Mapping(Mapping::new());
+        }
+        node = node
+            .as_mapping_mut()
+            .expect("node is a mapping")
+            .entry(Value::String(key.clone()))
+            .or_insert(Value::Mapping(Mapping::new()));
+    }
+    if !node.is_mapping() {
+        *node = Value::Mapping(Mapping::new());
+    }
+    node.as_mapping_mut()
+        .expect("node is a mapping")
+        .insert(Value::String(last.clone()), value);
+}
+
+/// 环境变量中的数字与布尔值按对应类型解析，其余一律视为字符串（如 PEM、URL）
+fn parse_env_value(value: &str) -> Value {
+    match serde_yaml::from_str::<Value>(value) {
+        Ok(v @ (Value::Number(_) | Value::Bool(_))) => v,
+        _ => Value::String(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn layered_config_should_follow_precedence() -> Result<()> {
+        let defaults: Value = serde_yaml::from_str("server:\n  port: 80\n  name: default")?;
+        let file: Value = serde_yaml::from_str("server:\n  port: 8080\n  host: file")?;
+        let vars = vec![
+            ("TEST__SERVER__PORT".to_string(), "9090".to_string()),
+            ("TEST__SERVER__DEBUG".to_string(), "true".to_string()),
+            ("OTHER__SERVER__HOST".to_string(), "ignored".to_string()),
+        ];
+        let config = LayeredConfig::from_layers(defaults, [file], vars, "TEST__");
+        assert_eq!(config.field::<u16>("server.port")?, 9090);
+        assert_eq!(config.field::<String>("server.name")?, "default");
+        assert_eq!(config.field::<String>("server.host")?, "file");
+        assert!(config.field::<bool>("server.debug")?);
+        assert_eq!(config.optional_field::<String>("server.missing")?, None);
+
+        let err = config.field::<String>("auth.key").unwrap_err();
+        assert!(err.to_string().contains("TEST__AUTH__KEY"));
+        let err = config.field::<u16>("server.host").unwrap_err();
+        assert!(err.to_string().contains("server.host"));
+        Ok(())
+    }
+
+    #[test]
+    fn layered_config_should_load_env_file_over_files() -> Result<()> {
+        let dir = env::temp_dir().join(format!("chat_core_layered_{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let file = dir.join("file.yml");
+        let from_env = dir.join("env.yml");
+        fs::write(&file, "name: file\nport: 1")?;
+        fs::write(&from_env, "name: env")?;
+        let files = [dir.join("missing.yml"), file];
+        let files: Vec<&str> = files.iter().map(|p| p.to_str().unwrap()).collect();
+        let env_key = "CHAT_CORE_TEST_LAYERED_CONFIG";
+
+        env::set_var(env_key, &from_env);
+        let config = LayeredConfig::load(Value::Null, &files, env_key, "CHAT_CORE_TEST__")?;
+        assert_eq!(config.field::<String>("name")?, "env");
+        assert_eq!(config.field::<u16>("port")?, 1);
+
+        env::set_var(env_key, dir.join("missing.yml"));
+        assert!(LayeredConfig::load(Value::Null, &files, env_key, "CHAT_CORE_TEST__").is_err());
+
+        env::remove_var(env_key);
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
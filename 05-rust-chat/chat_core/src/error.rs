@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+/// 统一的错误响应体：`{"error": "...", "code": "..."}`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ErrorOutput {
+    pub error: String,
+    pub code: String,
+}
+
+impl ErrorOutput {
+    pub fn new(error: impl Into<String>, code: impl Into<String>) -> Self {
+        Self {
+            error: error.into(),
+            code: code.into(),
+        }
+    }
+}
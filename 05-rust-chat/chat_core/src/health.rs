@@ -0,0 +1,80 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// `/readyz` 的响应体，所有检查项都通过时返回 200，否则返回 503
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Readiness {
+    pub ready: bool,
+    /// 检查项 => `ok` 或失败原因
+    pub checks: BTreeMap<String, String>,
+}
+
+impl Readiness {
+    /// 汇总各检查项的结果
+    ///
+    /// # 参数
+    /// * `checks` - (检查项, 检查结果)
+    ///
+    /// # 返回
+    /// * `Self` - 任一检查失败则 `ready` 为 false
+    pub fn new<E: fmt::Display>(
+        checks: impl IntoIterator<Item = (&'static str, Result<(), E>)>,
+    ) -> Self {
+        let mut ready = true;
+        let checks = checks
+            .into_iter()
+            .map(|(name, result)| {
+                let status = match result {
+                    Ok(()) => "ok".to_string(),
+                    Err(e) => {
+                        ready = false;
+                        e.to_string()
+                    }
+                };
+                (name.to_string(), status)
+            })
+            .collect();
+        Self { ready, checks }
+    }
+}
+
+impl IntoResponse for Readiness {
+    fn into_response(self) -> Response {
+        let status = if self.ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        (status, Json(self)).into_response()
+    }
+}
+
+/// 存活检查，进程能响应请求即返回 200
+pub async fn healthz_handler() -> impl IntoResponse {
+    "ok"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readiness_should_fail_if_any_check_fails() {
+        let readiness = Readiness::new([("database", Ok(())), ("listener", Err("disconnected"))]);
+        assert!(!readiness.ready);
+        assert_eq!(readiness.checks["database"], "ok");
+        assert_eq!(readiness.checks["listener"], "disconnected");
+        assert_eq!(
+            readiness.into_response().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        let readiness = Readiness::new([("database", Ok::<_, String>(()))]);
+        assert!(readiness.ready);
+        assert_eq!(readiness.into_response().status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,15 @@
+mod config;
+mod error;
+pub mod health;
+pub mod metrics;
+pub mod middlewares;
+mod models;
+mod telemetry;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+pub mod utils;
+
+pub use config::LayeredConfig;
+pub use error::ErrorOutput;
+pub use models::*;
+pub use telemetry::{init_tracing, LogFormat, TelemetryConfig, TelemetryGuard};
@@ -0,0 +1,226 @@
+use axum::extract::{MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// `/metrics` 响应的 Content-Type（Prometheus 文本格式 0.0.4）
+pub const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// 请求耗时直方图的桶上界（秒）
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// 按路由统计的 HTTP 请求数与耗时
+#[derive(Debug, Default)]
+pub struct HttpMetrics {
+    /// (method, route) => 该路由的统计
+    routes: Mutex<BTreeMap<(String, String), RouteMetrics>>,
+}
+
+#[derive(Debug, Default)]
+struct RouteMetrics {
+    /// 状态码 => 请求数
+    statuses: BTreeMap<u16, u64>,
+    /// 累计桶：耗时不超过对应上界的请求数
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl HttpMetrics {
+    /// 记录一次请求
+    ///
+    /// # 参数
+    /// * `method` - 请求方法
+    /// * `route` - 匹配到的路由模板（如 `/api/chat/:id`），避免按实际路径产生过多的标签
+    /// * `status` - 响应状态码
+    /// * `latency` - 请求耗时
+    pub fn observe(&self, method: &str, route: &str, status: u16, latency: Duration) {
+        let secs = latency.as_secs_f64();
+        let mut routes = self.routes.lock().expect("metrics lock poisoned");
+        let metrics = routes
+            .entry((method.to_string(), route.to_string()))
+            .or_default();
+        *metrics.statuses.entry(status).or_default() += 1;
+        for (bucket, le) in metrics.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if secs <= le {
+                *bucket += 1;
+            }
+        }
+        metrics.sum += secs;
+        metrics.count += 1;
+    }
+
+    /// 以 Prometheus 文本格式输出 `http_requests_total` 与 `http_request_duration_seconds`
+    ///
+    /// # 参数
+    /// * `out` - 输出缓冲区
+    pub fn encode(&self, out: &mut String) {
+        let routes = self.routes.lock().expect("metrics lock poisoned");
+        write_metric(
+            out,
+            "http_requests_total",
+            "counter",
+            "Total number of HTTP requests.",
+            routes.iter().flat_map(|((method, route), metrics)| {
+                metrics.statuses.iter().map(move |(status, count)| {
+                    (
+                        labels(&[
+                            ("method", method),
+                            ("route", route),
+                            ("status", &status.to_string()),
+                        ]),
+                        *count as f64,
+                    )
+                })
+            }),
+        );
+
+        let name = "http_request_duration_seconds";
+        let _ = writeln!(out, "# HELP {} HTTP request latency in seconds.", name);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for ((method, route), metrics) in routes.iter() {
+            for (count, le) in metrics.buckets.iter().zip(LATENCY_BUCKETS) {
+                let labels = labels(&[
+                    ("method", method),
+                    ("route", route),
+                    ("le", &le.to_string()),
+                ]);
+                let _ = writeln!(out, "{}_bucket{} {}", name, labels, count);
+            }
+            let labels_inf = labels(&[("method", method), ("route", route), ("le", "+Inf")]);
+            let _ = writeln!(out, "{}_bucket{} {}", name, labels_inf, metrics.count);
+            let labels = labels(&[("method", method), ("route", route)]);
+            let _ = writeln!(out, "{}_sum{} {}", name, labels, metrics.sum);
+            let _ = writeln!(out, "{}_count{} {}", name, labels, metrics.count);
+        }
+    }
+}
+
+/// 统计请求数与耗时的中间件
+/// 需通过 `Router::route_layer` 添加，以便拿到匹配的路由模板，未匹配到路由的请求不计入
+pub async fn track_metrics(
+    State(metrics): State<Arc<HttpMetrics>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(route) = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+    else {
+        return next.run(req).await;
+    };
+    let method = req.method().to_string();
+    let start = Instant::now();
+    let res = next.run(req).await;
+    metrics.observe(&method, &route, res.status().as_u16(), start.elapsed());
+    res
+}
+
+/// 以 Prometheus 文本格式输出一个 counter 或 gauge 指标
+///
+/// # 参数
+/// * `out` - 输出缓冲区
+/// * `name` - 指标名
+/// * `kind` - 指标类型，`counter` 或 `gauge`
+/// * `help` - 指标说明
+/// * `samples` - (标签, 值)，标签由 [`labels`] 生成，没有标签时为空字符串
+pub fn write_metric(
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    samples: impl IntoIterator<Item = (String, f64)>,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{}{} {}", name, labels, value);
+    }
+}
+
+/// 生成 `{key="value",...}` 形式的标签，对值中的反斜杠、引号和换行转义
+pub fn labels(pairs: &[(&str, &str)]) -> String {
+    let pairs: Vec<_> = pairs
+        .iter()
+        .map(|(key, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", key, value)
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use axum::body::Body;
+    use axum::middleware::from_fn_with_state;
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    #[test]
+    fn http_metrics_should_encode_counter_and_histogram() {
+        let metrics = HttpMetrics::default();
+        metrics.observe("GET", "/api/chat/:id", 200, Duration::from_millis(20));
+        metrics.observe("GET", "/api/chat/:id", 200, Duration::from_millis(300));
+        metrics.observe("GET", "/api/chat/:id", 404, Duration::from_millis(1));
+
+        let mut out = String::new();
+        metrics.encode(&mut out);
+        assert!(out.contains("# TYPE http_requests_total counter"));
+        assert!(out
+            .contains(r#"http_requests_total{method="GET",route="/api/chat/:id",status="200"} 2"#));
+        assert!(out
+            .contains(r#"http_requests_total{method="GET",route="/api/chat/:id",status="404"} 1"#));
+        assert!(out.contains(
+            r#"http_request_duration_seconds_bucket{method="GET",route="/api/chat/:id",le="0.025"} 2"#
+        ));
+        assert!(out.contains(
+            r#"http_request_duration_seconds_bucket{method="GET",route="/api/chat/:id",le="+Inf"} 3"#
+        ));
+        assert!(out.contains(
+            r#"http_request_duration_seconds_count{method="GET",route="/api/chat/:id"} 3"#
+        ));
+    }
+
+    #[test]
+    fn labels_should_escape_values() {
+        assert_eq!(labels(&[]), "{}");
+        assert_eq!(
+            labels(&[("a", "x"), ("b", "say \"hi\"\n")]),
+            r#"{a="x",b="say \"hi\"\n"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn track_metrics_should_record_matched_route() -> Result<()> {
+        let metrics = Arc::new(HttpMetrics::default());
+        let app = Router::new()
+            .route("/chat/:id", get(|| async { "ok" }))
+            .route_layer(from_fn_with_state(metrics.clone(), track_metrics));
+
+        let req = Request::builder().uri("/chat/1").body(Body::empty())?;
+        app.clone().oneshot(req).await?;
+        let req = Request::builder().uri("/not-found").body(Body::empty())?;
+        app.oneshot(req).await?;
+
+        let mut out = String::new();
+        metrics.encode(&mut out);
+        assert!(
+            out.contains(r#"http_requests_total{method="GET",route="/chat/:id",status="200"} 1"#)
+        );
+        assert!(!out.contains("/not-found"));
+        Ok(())
+    }
+}
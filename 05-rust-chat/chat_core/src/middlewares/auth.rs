@@ -0,0 +1,201 @@
+use crate::utils::TokenClaims;
+use crate::{ErrorOutput, User};
+use axum::async_trait;
+use axum::extract::{FromRequestParts, Query, Request, State};
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use axum_extra::headers::authorization::Bearer;
+use axum_extra::headers::Authorization;
+use axum_extra::TypedHeader;
+use serde::Deserialize;
+use std::fmt;
+use tracing::{warn, Span};
+
+/// 校验 token 并解析出其中的声明，由各服务的 AppState 实现，可在此检查 token 是否已被吊销
+#[async_trait]
+pub trait TokenVerify {
+    type Error: fmt::Display;
+
+    async fn verify(&self, token: &str) -> Result<TokenClaims, Self::Error>;
+}
+
+/// 当前登录用户，由 [`verify_token`] 中间件写入请求扩展
+#[derive(Debug, Clone)]
+pub struct CurrentUser(pub User);
+
+#[derive(Debug, Deserialize)]
+struct AccessToken {
+    access_token: String,
+}
+
+/// 鉴权中间件
+/// 读取 `Authorization: Bearer <token>` 头，校验通过后将 [`User`] 与 [`TokenClaims`]
+/// 写入请求扩展，并把 user_id 记录到当前请求的 span 上，否则返回 401
+pub async fn verify_token<T>(State(state): State<T>, req: Request, next: Next) -> Response
+where
+    T: TokenVerify + Clone + Send + Sync + 'static,
+{
+    authenticate(state, req, next, false).await
+}
+
+/// 允许查询参数传递 token 的鉴权中间件
+/// 浏览器的 EventSource 无法设置请求头，没有 Authorization 头时读取 `?access_token=<token>`；
+/// URL 中的 token 会出现在访问日志与代理中，只应挂在 SSE 这类必须使用的路由上，其余同 [`verify_token`]
+pub async fn verify_token_or_query<T>(State(state): State<T>, req: Request, next: Next) -> Response
+where
+    T: TokenVerify + Clone + Send + Sync + 'static,
+{
+    authenticate(state, req, next, true).await
+}
+
+async fn authenticate<T>(state: T, req: Request, next: Next, allow_query: bool) -> Response
+where
+    T: TokenVerify + Clone + Send + Sync + 'static,
+{
+    let (mut parts, body) = req.into_parts();
+    let token =
+        match TypedHeader::<Authorization<Bearer>>::from_request_parts(&mut parts, &state).await {
+            Ok(TypedHeader(Authorization(bearer))) => bearer.token().to_string(),
+            Err(e) => match Query::<AccessToken>::from_request_parts(&mut parts, &state).await {
+                Ok(Query(query)) if allow_query => query.access_token,
+                _ => {
+                    let msg = format!("parse Authorization header failed: {}", e);
+                    warn!(msg);
+                    return unauthorized(msg);
+                }
+            },
+        };
+
+    let claims = match state.verify(&token).await {
+        Ok(claims) => claims,
+        Err(e) => {
+            let msg = format!("verify token failed: {}", e);
+            warn!(msg);
+            return unauthorized(msg);
+        }
+    };
+
+    Span::current().record("user_id", claims.user.id);
+    let mut req = Request::from_parts(parts, body);
+    req.extensions_mut().insert(claims.user.clone());
+    req.extensions_mut().insert(claims);
+    next.run(req).await
+}
+
+fn unauthorized(msg: String) -> Response {
+    let body = ErrorOutput::new(format!("unauthorized: {}", msg), "unauthorized");
+    (StatusCode::UNAUTHORIZED, Json(body)).into_response()
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CurrentUser
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<User>()
+            .cloned()
+            .map(CurrentUser)
+            .ok_or_else(|| unauthorized("missing current user".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{DecodingKey, EncodingKey};
+    use anyhow::Result;
+    use axum::body::Body;
+    use axum::middleware::from_fn_with_state;
+    use axum::routing::get;
+    use axum::Router;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    #[derive(Clone)]
+    struct AppState(Arc<DecodingKey>);
+
+    #[async_trait]
+    impl TokenVerify for AppState {
+        type Error = jwt_simple::Error;
+
+        async fn verify(&self, token: &str) -> Result<TokenClaims, Self::Error> {
+            self.0.decode(token)
+        }
+    }
+
+    async fn handler(CurrentUser(user): CurrentUser) -> impl IntoResponse {
+        (StatusCode::OK, user.email)
+    }
+
+    #[tokio::test]
+    async fn verify_token_middleware_should_work() -> Result<()> {
+        let ek = EncodingKey::load(include_str!("../../fixtures/encoding.pem"))?;
+        let dk = DecodingKey::load(include_str!("../../fixtures/decoding.pem"))?;
+        let state = AppState(Arc::new(dk));
+        let user = User {
+            id: 1,
+            ws_id: 1,
+            fullname: "zhangSan".to_string(),
+            email: "test@mail.com".to_string(),
+            password_hash: None,
+            created_at: chrono::Utc::now(),
+        };
+        let token = ek.sign(user)?;
+
+        let app = Router::new()
+            .route("/", get(handler))
+            .layer(from_fn_with_state(state.clone(), verify_token::<AppState>))
+            .route(
+                "/events",
+                get(handler).layer(from_fn_with_state(
+                    state.clone(),
+                    verify_token_or_query::<AppState>,
+                )),
+            )
+            .with_state(state);
+
+        // token 放在 Authorization 头
+        let req = Request::builder()
+            .uri("/")
+            .header("Authorization", format!("Bearer {}", token))
+            .body(Body::empty())?;
+        let res = app.clone().oneshot(req).await?;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // 默认不接受查询参数中的 token
+        let req = Request::builder()
+            .uri(format!("/?access_token={}", token))
+            .body(Body::empty())?;
+        let res = app.clone().oneshot(req).await?;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        // 显式允许的路由可以把 token 放在查询参数
+        let req = Request::builder()
+            .uri(format!("/events?access_token={}", token))
+            .body(Body::empty())?;
+        let res = app.clone().oneshot(req).await?;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // 缺少 token
+        let req = Request::builder().uri("/").body(Body::empty())?;
+        let res = app.clone().oneshot(req).await?;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        // 非法 token
+        let req = Request::builder()
+            .uri("/")
+            .header("Authorization", "Bearer bad-token")
+            .body(Body::empty())?;
+        let res = app.oneshot(req).await?;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        Ok(())
+    }
+}
@@ -0,0 +1,5 @@
+mod auth;
+mod request_id;
+
+pub use auth::*;
+pub use request_id::*;
@@ -0,0 +1,91 @@
+use axum::body::Body;
+use axum::http::{HeaderName, HeaderValue, Request};
+use axum::Router;
+use jwt_simple::reexports::rand::{thread_rng, RngCore};
+use tower_http::request_id::{
+    MakeRequestId, PropagateRequestIdLayer, RequestId, SetRequestIdLayer,
+};
+use tower_http::trace::{DefaultOnResponse, TraceLayer};
+use tracing::{info_span, Level, Span};
+
+/// 请求 ID 所在的请求头与响应头
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// 为没有携带 `x-request-id` 的请求生成 32 位十六进制的随机 ID
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MakeRandomRequestId;
+
+impl MakeRequestId for MakeRandomRequestId {
+    fn make_request_id<B>(&mut self, _req: &Request<B>) -> Option<RequestId> {
+        let mut rng = thread_rng();
+        let id = format!("{:016x}{:016x}", rng.next_u64(), rng.next_u64());
+        HeaderValue::from_str(&id).ok().map(RequestId::new)
+    }
+}
+
+/// 为路由加上请求 ID 与请求追踪
+/// 沿用客户端传入的 `x-request-id`，没有则生成一个，并在响应头中原样返回；
+/// 每个请求一个 `request` span，记录 method、path、request_id，鉴权通过后由
+/// [`verify_token`](super::verify_token) 补上 user_id
+///
+/// # 参数
+/// * `app` - 需要追踪的路由
+///
+/// # 返回
+/// * `Router<S>` - 加上追踪层后的路由
+pub fn set_layer<S>(app: Router<S>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let header = HeaderName::from_static(REQUEST_ID_HEADER);
+    // 后加的层在外层：先设置请求 ID，再创建 span，最后把请求 ID 写回响应
+    app.layer(PropagateRequestIdLayer::new(header.clone()))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(make_span)
+                .on_response(DefaultOnResponse::new().level(Level::INFO)),
+        )
+        .layer(SetRequestIdLayer::new(header, MakeRandomRequestId))
+}
+
+fn make_span(req: &Request<Body>) -> Span {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    info_span!(
+        "request",
+        method = %req.method(),
+        path = %req.uri().path(),
+        request_id,
+        user_id = tracing::field::Empty,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use axum::routing::get;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn request_id_should_be_generated_or_propagated() -> Result<()> {
+        let app = set_layer(Router::new().route("/", get(|| async { "ok" })));
+
+        let req = Request::builder().uri("/").body(Body::empty())?;
+        let res = app.clone().oneshot(req).await?;
+        let id = res.headers().get(REQUEST_ID_HEADER).unwrap().to_str()?;
+        assert_eq!(id.len(), 32);
+        assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+
+        let req = Request::builder()
+            .uri("/")
+            .header(REQUEST_ID_HEADER, "abc-123")
+            .body(Body::empty())?;
+        let res = app.oneshot(req).await?;
+        assert_eq!(res.headers().get(REQUEST_ID_HEADER).unwrap(), "abc-123");
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+use anyhow::Result;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+/// 日志与链路追踪配置，各服务配置文件中的 `telemetry` 段
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TelemetryConfig {
+    /// 日志过滤规则，语法同 RUST_LOG，设置了 RUST_LOG 环境变量时以环境变量为准
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
+    /// 日志输出格式：text 便于本地阅读，json 便于日志系统采集
+    #[serde(default)]
+    pub log_format: LogFormat,
+    /// OTLP collector 的 gRPC 地址（如 http://localhost:4317），不配置则不导出链路数据
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// 链路导出的守卫，drop 时把缓冲中的 span 发送出去并关闭导出器
+#[must_use = "dropping the guard shuts down the OTLP exporter"]
+pub struct TelemetryGuard {
+    otlp: bool,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            log_level: default_log_level(),
+            log_format: LogFormat::default(),
+            otlp_endpoint: None,
+        }
+    }
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+/// 初始化日志与链路追踪
+/// 按配置输出 text 或 json 格式的日志，配置了 otlp_endpoint 时再把 span 通过 OTLP 导出
+///
+/// # 参数
+/// * `service_name` - 上报到 OTLP 的 service.name
+/// * `config` - 日志与链路追踪配置
+///
+/// # 返回
+/// * `Result<TelemetryGuard>` - 需在服务运行期间持有，失败则返回错误
+pub fn init_tracing(service_name: &str, config: &TelemetryConfig) -> Result<TelemetryGuard> {
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => EnvFilter::try_new(&config.log_level)?,
+    };
+    let (text, json) = match config.log_format {
+        LogFormat::Text => (Some(fmt::layer()), None),
+        LogFormat::Json => (
+            None,
+            Some(
+                fmt::layer()
+                    .json()
+                    .flatten_event(true)
+                    .with_span_list(false),
+            ),
+        ),
+    };
+    let otlp = match &config.otlp_endpoint {
+        Some(endpoint) => {
+            Some(tracing_opentelemetry::layer().with_tracer(init_tracer(service_name, endpoint)?))
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(text)
+        .with(json)
+        .with(otlp)
+        .try_init()?;
+
+    Ok(TelemetryGuard {
+        otlp: config.otlp_endpoint.is_some(),
+    })
+}
+
+fn init_tracer(service_name: &str, endpoint: &str) -> Result<trace::Tracer> {
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                service_name.to_string(),
+            )])),
+        )
+        .install_batch(runtime::Tokio)?;
+    Ok(tracer)
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if self.otlp {
+            opentelemetry::global::shutdown_tracer_provider();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn telemetry_config_should_default_to_offline_text_logs() -> Result<()> {
+        let config: TelemetryConfig = serde_yaml::from_str("log_format: json")?;
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(config.log_level, "info");
+        assert_eq!(config.otlp_endpoint, None);
+
+        let config = TelemetryConfig::default();
+        assert_eq!(config.log_format, LogFormat::Text);
+        assert!(serde_yaml::from_str::<TelemetryConfig>("log_format: xml").is_err());
+        Ok(())
+    }
+}
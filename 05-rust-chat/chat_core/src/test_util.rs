@@ -0,0 +1,121 @@
+use sqlx::migrate::Migrator;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 测试用的临时数据库
+/// 在 `DATABASE_URL` 所在的 Postgres 上新建独立的数据库，执行迁移并加载测试数据，
+/// 离开作用域时删除该数据库，测试之间互不影响
+pub struct TestPg {
+    server_url: String,
+    options: PgConnectOptions,
+    dbname: String,
+}
+
+impl TestPg {
+    pub async fn new(database_url: &str) -> anyhow::Result<Self> {
+        static SEQ: AtomicUsize = AtomicUsize::new(0);
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+        let dbname = format!(
+            "test_chat_{}_{}_{}",
+            std::process::id(),
+            nanos,
+            SEQ.fetch_add(1, Ordering::Relaxed)
+        );
+        let options = PgConnectOptions::from_str(database_url)?;
+        let server_url = match database_url.rsplit_once('/') {
+            Some((server_url, _)) => server_url.to_string(),
+            None => anyhow::bail!("invalid database url: {}", database_url),
+        };
+
+        let mut conn = PgConnection::connect_with(&options).await?;
+        conn.execute(format!(r#"CREATE DATABASE "{}""#, dbname).as_str())
+            .await?;
+        conn.close().await?;
+
+        let tdb = Self {
+            server_url,
+            options,
+            dbname,
+        };
+        let pool = tdb.get_pool().await?;
+        let migrations = Path::new(env!("CARGO_MANIFEST_DIR")).join("../migrations");
+        Migrator::new(migrations).await?.run(&pool).await?;
+        pool.execute(include_str!("../../chat_server/fixtures/test.sql"))
+            .await?;
+        pool.close().await;
+        Ok(tdb)
+    }
+
+    /// 临时数据库的连接地址
+    pub fn url(&self) -> String {
+        format!("{}/{}", self.server_url, self.dbname)
+    }
+
+    pub async fn get_pool(&self) -> anyhow::Result<PgPool> {
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect_with(self.options.clone().database(&self.dbname))
+            .await?;
+        Ok(pool)
+    }
+}
+
+impl Drop for TestPg {
+    fn drop(&mut self) {
+        let options = self.options.clone();
+        let dbname = self.dbname.clone();
+        // Drop 不能是异步的，在独立线程中用新的运行时删除数据库
+        thread::spawn(move || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("build runtime");
+            rt.block_on(async move {
+                let mut conn = PgConnection::connect_with(&options)
+                    .await
+                    .expect("connect to postgres");
+                conn.execute(format!(r#"DROP DATABASE "{}" WITH (FORCE)"#, dbname).as_str())
+                    .await
+                    .expect("drop test database");
+            });
+        })
+        .join()
+        .expect("drop test database thread");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[tokio::test]
+    async fn test_pg_should_create_and_drop_database() -> Result<()> {
+        dotenvy::dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let tdb = TestPg::new(&database_url).await?;
+        let dbname = tdb.dbname.clone();
+
+        let pool = tdb.get_pool().await?;
+        let (count,): (i64,) = sqlx::query_as("SELECT count(*) FROM users")
+            .fetch_one(&pool)
+            .await?;
+        assert_eq!(count, 3);
+        pool.close().await;
+        drop(tdb);
+
+        let mut conn = PgConnection::connect(&database_url).await?;
+        let (exists,): (bool,) =
+            sqlx::query_as("SELECT EXISTS(SELECT 1 FROM pg_database WHERE datname = $1)")
+                .bind(&dbname)
+                .fetch_one(&mut conn)
+                .await?;
+        assert!(!exists);
+        Ok(())
+    }
+}
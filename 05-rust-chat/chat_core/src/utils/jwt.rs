@@ -0,0 +1,250 @@
+use crate::User;
+use chrono::{DateTime, Utc};
+use jwt_simple::prelude::*;
+use jwt_simple::reexports::rand::{thread_rng, RngCore};
+use jwt_simple::JWTError;
+use std::collections::HashMap;
+
+/// access token 有效期，过期后需用 refresh token 换取新的 access token
+pub const ACCESS_TOKEN_DURATION: u64 = 60 * 15;
+const JWT_ISS: &str = "chat_server";
+
+const JWT_AUD: &str = "chat_web";
+
+/// 签名私钥，签发的 token 头部带有该密钥的 `kid`
+pub struct EncodingKey {
+    kid: String,
+    key: Ed25519KeyPair,
+}
+
+/// 校验公钥集合，按 token 头部的 `kid` 选择公钥，轮换密钥期间新旧公钥同时有效
+#[derive(Debug, Clone)]
+pub struct DecodingKey {
+    keys: HashMap<String, Ed25519PublicKey>,
+}
+
+/// 校验通过的 access token，`jti` 用于吊销
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenClaims {
+    pub user: User,
+    pub jti: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// JSON Web Key Set，即 `/.well-known/jwks.json` 的响应体
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+/// Ed25519 公钥的 JWK 表示（RFC 8037）
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Jwk {
+    pub kty: String,
+    pub crv: String,
+    pub alg: String,
+    #[serde(rename = "use")]
+    pub r#use: String,
+    pub kid: String,
+    pub x: String,
+}
+
+impl EncodingKey {
+    pub fn load(pem: &str) -> Result<Self, jwt_simple::Error> {
+        let key = Ed25519KeyPair::from_pem(pem)?;
+        let kid = key_id(&key.public_key());
+        Ok(Self {
+            key: key.with_key_id(&kid),
+            kid,
+        })
+    }
+
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+
+    pub fn sign(&self, user: impl Into<User>) -> Result<String, jwt_simple::Error> {
+        let claims =
+            Claims::with_custom_claims(user.into(), Duration::from_secs(ACCESS_TOKEN_DURATION));
+        let claims = claims
+            .with_issuer(JWT_ISS)
+            .with_audience(JWT_AUD)
+            .with_jwt_id(new_jti());
+        self.key.sign(claims)
+    }
+}
+
+impl DecodingKey {
+    pub fn load(pem: &str) -> Result<Self, jwt_simple::Error> {
+        Self::load_all([pem])
+    }
+
+    /// 加载多个公钥，通常为当前签名密钥与尚未过期的旧密钥
+    pub fn load_all<'a>(
+        pems: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self, jwt_simple::Error> {
+        let keys = pems
+            .into_iter()
+            .map(|pem| {
+                let key = Ed25519PublicKey::from_pem(pem)?;
+                Ok((key_id(&key), key))
+            })
+            .collect::<Result<HashMap<_, _>, jwt_simple::Error>>()?;
+        Ok(Self { keys })
+    }
+
+    /// 从 JWKS 构造公钥集合，忽略非 Ed25519 的公钥
+    pub fn from_jwks(jwks: &Jwks) -> Result<Self, jwt_simple::Error> {
+        let mut keys = HashMap::new();
+        for jwk in jwks
+            .keys
+            .iter()
+            .filter(|k| k.kty == "OKP" && k.crv == "Ed25519")
+        {
+            let raw = Base64UrlSafeNoPadding::decode_to_vec(&jwk.x, None)?;
+            keys.insert(jwk.kid.clone(), Ed25519PublicKey::from_bytes(&raw)?);
+        }
+        if keys.is_empty() {
+            return Err(JWTError::InvalidPublicKey.into());
+        }
+        Ok(Self { keys })
+    }
+
+    pub fn jwks(&self) -> Jwks {
+        let mut keys: Vec<Jwk> = self
+            .keys
+            .iter()
+            .map(|(kid, key)| Jwk {
+                kty: "OKP".to_string(),
+                crv: "Ed25519".to_string(),
+                alg: "EdDSA".to_string(),
+                r#use: "sig".to_string(),
+                kid: kid.clone(),
+                x: Base64UrlSafeNoPadding::encode_to_string(key.to_bytes())
+                    .expect("encode public key"),
+            })
+            .collect();
+        keys.sort_by(|a, b| a.kid.cmp(&b.kid));
+        Jwks { keys }
+    }
+
+    pub fn verify(&self, token: &str) -> Result<User, jwt_simple::Error> {
+        Ok(self.decode(token)?.user)
+    }
+
+    pub fn decode(&self, token: &str) -> Result<TokenClaims, jwt_simple::Error> {
+        let opts = VerificationOptions {
+            allowed_issuers: Some(HashSet::from_strings(&[JWT_ISS])),
+            allowed_audiences: Some(HashSet::from_strings(&[JWT_AUD])),
+            ..Default::default()
+        };
+
+        // 没有 kid 的旧 token 只能在仅有一个公钥时校验
+        let metadata = Token::decode_metadata(token)?;
+        let key = match metadata.key_id() {
+            Some(kid) => self.keys.get(kid).ok_or(JWTError::KeyIdentifierMismatch)?,
+            None if self.keys.len() == 1 => self.keys.values().next().expect("one key"),
+            None => return Err(JWTError::MissingJWTKeyIdentifier.into()),
+        };
+        let claims = key.verify_token::<User>(token, Some(opts))?;
+        let expires_at = claims
+            .expires_at
+            .and_then(|exp| DateTime::from_timestamp(exp.as_secs() as i64, 0))
+            .ok_or(JWTError::TokenHasExpired)?;
+        Ok(TokenClaims {
+            user: claims.custom,
+            jti: claims
+                .jwt_id
+                .ok_or_else(|| JWTError::InternalError("missing jti".to_string()))?,
+            expires_at,
+        })
+    }
+}
+
+/// 以公钥的 sha256 摘要作为 kid，同一公钥在各服务中得到相同的 kid
+fn key_id(key: &Ed25519PublicKey) -> String {
+    let mut key = key.clone();
+    key.create_key_id().to_string()
+}
+
+fn new_jti() -> String {
+    let mut buf = [0u8; 16];
+    thread_rng().fill_bytes(&mut buf);
+    buf.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[tokio::test]
+    async fn jwt_sign_verify_should_work() -> Result<()> {
+        let encoding_pem = include_str!("../../fixtures/encoding.pem");
+        let decoding_pem = include_str!("../../fixtures/decoding.pem");
+        let ek = EncodingKey::load(encoding_pem)?;
+        let dk = DecodingKey::load(decoding_pem)?;
+
+        let user = User {
+            id: 1,
+            ws_id: 1,
+            fullname: "zhangSan".to_string(),
+            email: "test@mail.com".to_string(),
+            password_hash: None,
+            created_at: chrono::Utc::now(),
+        };
+
+        let token = ek.sign(user.clone())?;
+        let user2 = dk.verify(&token)?;
+        assert_eq!(user, user2);
+
+        // 每个 token 的 jti 都不相同
+        let claims = dk.decode(&token)?;
+        let other = dk.decode(&ek.sign(user)?)?;
+        assert_eq!(claims.jti.len(), 32);
+        assert_ne!(claims.jti, other.jti);
+        assert!(claims.expires_at > chrono::Utc::now());
+        Ok(())
+    }
+
+    #[test]
+    fn key_rotation_should_work() -> Result<()> {
+        let old_ek = EncodingKey::load(include_str!("../../fixtures/encoding.pem"))?;
+        let old_pem = include_str!("../../fixtures/decoding.pem");
+        let new_key = Ed25519KeyPair::generate();
+        let new_ek = EncodingKey::load(&new_key.to_pem())?;
+        let new_pem = new_key.public_key().to_pem();
+
+        let user = User {
+            id: 1,
+            ws_id: 1,
+            fullname: "zhangSan".to_string(),
+            email: "test@mail.com".to_string(),
+            password_hash: None,
+            created_at: chrono::Utc::now(),
+        };
+        let old_token = old_ek.sign(user.clone())?;
+        let new_token = new_ek.sign(user)?;
+        assert_eq!(
+            Token::decode_metadata(&new_token)?.key_id(),
+            Some(new_ek.kid())
+        );
+
+        // 只有旧公钥时不认新 token
+        let dk = DecodingKey::load(old_pem)?;
+        assert!(dk.decode(&new_token).is_err());
+
+        // 轮换期间新旧 token 都有效
+        let dk = DecodingKey::load_all([old_pem, new_pem.as_str()])?;
+        assert!(dk.decode(&old_token).is_ok());
+        assert!(dk.decode(&new_token).is_ok());
+
+        // 通过 JWKS 分发的公钥同样可以校验
+        let jwks = dk.jwks();
+        assert_eq!(jwks.keys.len(), 2);
+        let dk = DecodingKey::from_jwks(&serde_json::from_str(&serde_json::to_string(&jwks)?)?)?;
+        assert!(dk.decode(&old_token).is_ok());
+        assert!(dk.decode(&new_token).is_ok());
+        Ok(())
+    }
+}
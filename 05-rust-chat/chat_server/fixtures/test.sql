@@ -0,0 +1,20 @@
+-- test fixtures, loaded into every throwaway test database after migrations
+-- migrations create no rows, every statement is idempotent so the file can be loaded more than once
+INSERT INTO workspaces (id, name, owner_id)
+VALUES (1, 'default', 0)
+ON CONFLICT (id) DO NOTHING;
+SELECT setval('workspaces_id_seq', (SELECT max(id) FROM workspaces));
+
+-- 插入测试用户数据
+INSERT INTO users (ws_id, fullname, email, password_hash)
+VALUES (1, '张三', 'zhangsan@example.com',
+        '$argon2id$v=19$m=4096,t=3,p=1$waSRM7HJw7xMIxlG$WDpOvt9hXYRjVyYqgyVx3AcE6lS5DE/Jb6TRfs+BA9w'),
+       (1, '李四', 'lisi@example.com',
+        '$argon2id$v=19$m=4096,t=3,p=1$waSRM7HJw7xMIxlG$WDpOvt9hXYRjVyYqgyVx3AcE6lS5DE/Jb6TRfs+BA9w'),
+       (1, '王五', 'wangwu@example.com',
+        '$argon2id$v=19$m=4096,t=3,p=1$waSRM7HJw7xMIxlG$WDpOvt9hXYRjVyYqgyVx3AcE6lS5DE/Jb6TRfs+BA9w')
+ON CONFLICT (email) DO NOTHING;
+
+UPDATE workspaces
+SET owner_id = 1
+WHERE id = 1;
@@ -1,31 +1,306 @@
-use std::env;
-use std::fs::File;
+use crate::rate_limit::Quota;
 use anyhow::{bail, Result};
+use chat_core::{LayeredConfig, TelemetryConfig};
 use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::path::PathBuf;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// 可被 `CHAT__SECTION__KEY` 形式的环境变量覆盖的配置前缀
+const ENV_PREFIX: &str = "CHAT__";
+const REDACTED: &str = "<redacted>";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
     pub server: ServerConfig,
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthConfig {
+    pub sk: String,
+    pub pk: String,
+    /// 已轮换下来的旧公钥，在其签发的 token 全部过期前仍用于校验
+    #[serde(default)]
+    pub previous_pks: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub port: u16,
+    pub db_url: String,
+    /// 上传文件的存储根目录，按工作空间分目录保存
+    pub base_dir: PathBuf,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// 登录、注册、刷新 token 等公开接口按客户端 IP 的配额
+    pub ip: Quota,
+    /// 发送消息按用户的配额
+    pub user: Quota,
+    /// 同一邮箱连续登录失败多少次后锁定
+    pub max_failed_signins: u32,
+    /// 锁定时长（秒），也是累计失败次数的统计窗口
+    pub lockout_secs: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            ip: Quota {
+                burst: 10,
+                per_minute: 30,
+            },
+            user: Quota {
+                burst: 20,
+                per_minute: 60,
+            },
+            max_failed_signins: 5,
+            lockout_secs: 900,
+        }
+    }
+}
+
 impl AppConfig {
-    #[allow(dead_code)]
+    /// 加载配置
+    /// 按 默认值 < ./app.yml 或 /etc/config/app.yml < CHAT_CONFIG 指定的文件 < `CHAT__SERVER__PORT`
+    /// 形式的环境变量 的优先级逐层合并，合并后校验必填项与取值
+    ///
+    /// # 返回
+    /// * `Result<Self>` - 成功则返回最终生效的配置，失败则返回指明出错配置项的错误
     pub fn load() -> Result<Self> {
-        // read from  ./app.yml, or /etc/config/app.yml, or from env CHAT_CONFIG
-        let ret = match (
-            File::open("app.yml"),
-            File::open("/etc/config/app.yml"),
-            env::var("CHAT_CONFIG"),
-        ) {
-            (Ok(reader), _, _) => serde_yaml::from_reader(reader),
-            (_, Ok(reader), _) => serde_yaml::from_reader(reader),
-            (_, _, Ok(path)) => serde_yaml::from_reader(File::open(path)?),
-            _ => bail!("Config file not found"),
+        let config = LayeredConfig::load(
+            defaults(),
+            &["app.yml", "/etc/config/app.yml"],
+            "CHAT_CONFIG",
+            ENV_PREFIX,
+        )?;
+        Self::from_config(&config)
+    }
+
+    /// 合并配置
+    /// 以默认值为底，依次合并配置文件与环境变量，再读取各配置项并校验
+    ///
+    /// # 参数
+    /// * `layers` - 按优先级从低到高排列的配置文件内容
+    /// * `vars` - 环境变量，只有以 `CHAT__` 开头的会生效
+    ///
+    /// # 返回
+    /// * `Result<Self>` - 成功则返回合并后的配置，失败则返回错误
+    #[cfg(test)]
+    fn from_layers(
+        layers: impl IntoIterator<Item = Value>,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self> {
+        Self::from_config(&LayeredConfig::from_layers(
+            defaults(),
+            layers,
+            vars,
+            ENV_PREFIX,
+        ))
+    }
+
+    fn from_config(merged: &LayeredConfig) -> Result<Self> {
+        let config = Self {
+            server: ServerConfig {
+                port: merged.field("server.port")?,
+                db_url: merged.field("server.db_url")?,
+                base_dir: merged.field("server.base_dir")?,
+            },
+            auth: AuthConfig {
+                sk: merged.field("auth.sk")?,
+                pk: merged.field("auth.pk")?,
+                previous_pks: merged
+                    .optional_field("auth.previous_pks")?
+                    .unwrap_or_default(),
+            },
+            rate_limit: RateLimitConfig {
+                ip: merged.field("rate_limit.ip")?,
+                user: merged.field("rate_limit.user")?,
+                max_failed_signins: merged.field("rate_limit.max_failed_signins")?,
+                lockout_secs: merged.field("rate_limit.lockout_secs")?,
+            },
+            telemetry: TelemetryConfig {
+                log_level: merged.field("telemetry.log_level")?,
+                log_format: merged.field("telemetry.log_format")?,
+                otlp_endpoint: merged.optional_field("telemetry.otlp_endpoint")?,
+            },
         };
-        Ok(ret?)
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.server.port == 0 {
+            bail!("invalid config key `server.port`: must be greater than 0");
+        }
+        if !self.server.db_url.starts_with("postgres://")
+            && !self.server.db_url.starts_with("postgresql://")
+        {
+            bail!("invalid config key `server.db_url`: must be a postgres:// url");
+        }
+        if self.rate_limit.ip.burst == 0 || self.rate_limit.user.burst == 0 {
+            bail!("invalid config key `rate_limit`: burst must be greater than 0");
+        }
+        if self.rate_limit.max_failed_signins == 0 {
+            bail!("invalid config key `rate_limit.max_failed_signins`: must be greater than 0");
+        }
+        if !self.auth.sk.contains("PRIVATE KEY") {
+            bail!("invalid config key `auth.sk`: must be a PEM encoded private key");
+        }
+        if !self.auth.pk.contains("PUBLIC KEY") {
+            bail!("invalid config key `auth.pk`: must be a PEM encoded public key");
+        }
+        if self
+            .auth
+            .previous_pks
+            .iter()
+            .any(|pk| !pk.contains("PUBLIC KEY"))
+        {
+            bail!("invalid config key `auth.previous_pks`: must be PEM encoded public keys");
+        }
+        Ok(())
+    }
+
+    /// 隐藏敏感信息后的配置，用于 `--check-config` 输出
+    ///
+    /// # 返回
+    /// * `Self` - 私钥与数据库密码被替换为 `<redacted>` 的配置副本
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        config.auth.sk = REDACTED.to_string();
+        config.server.db_url = redact_url_password(&config.server.db_url);
+        config
+    }
+}
+
+fn defaults() -> Value {
+    let mut value: Value = serde_yaml::from_str(
+        r#"
+        server:
+          port: 8080
+          base_dir: /tmp/chat_server
+        "#,
+    )
+    .expect("default config must be valid yaml");
+    value["rate_limit"] = serde_yaml::to_value(RateLimitConfig::default())
+        .expect("rate limit config is serializable");
+    value["telemetry"] =
+        serde_yaml::to_value(TelemetryConfig::default()).expect("telemetry config is serializable");
+    value
+}
+
+fn redact_url_password(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+    match rest.split_once('@') {
+        Some((userinfo, host)) => match userinfo.split_once(':') {
+            Some((user, _)) => format!("{}://{}:{}@{}", scheme, user, REDACTED, host),
+            None => url.to_string(),
+        },
+        None => url.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app_yml() -> Value {
+        serde_yaml::from_str(include_str!("../../app.yml")).unwrap()
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn app_yml_should_parse() -> Result<()> {
+        let config: AppConfig = serde_yaml::from_str(include_str!("../../app.yml"))?;
+        assert_eq!(config.server.port, 8080);
+        assert!(config.auth.sk.contains("PRIVATE KEY"));
+        assert!(config.auth.pk.contains("PUBLIC KEY"));
+        Ok(())
+    }
+
+    #[test]
+    fn layers_should_follow_precedence() -> Result<()> {
+        let override_file: Value =
+            serde_yaml::from_str("server:\n  port: 9090\n  base_dir: /data")?;
+        let config = AppConfig::from_layers(
+            [app_yml(), override_file],
+            vars(&[("CHAT__SERVER__PORT", "9191"), ("CHAT_CONFIG", "ignored")]),
+        )?;
+        // 环境变量优先于 CHAT_CONFIG 文件，CHAT_CONFIG 文件优先于 app.yml
+        assert_eq!(config.server.port, 9191);
+        assert_eq!(config.server.base_dir, PathBuf::from("/data"));
+        assert!(config.server.db_url.starts_with("postgres://"));
+        Ok(())
+    }
+
+    #[test]
+    fn defaults_should_fill_missing_keys() -> Result<()> {
+        let mut file = app_yml();
+        file["server"].as_mapping_mut().unwrap().remove("port");
+        let config =
+            AppConfig::from_layers([file], vars(&[("CHAT__RATE_LIMIT__USER__BURST", "5")]))?;
+        assert_eq!(config.server.port, 8080);
+        assert_eq!(config.rate_limit.user.burst, 5);
+        assert_eq!(config.rate_limit.ip, RateLimitConfig::default().ip);
+        assert_eq!(config.telemetry, TelemetryConfig::default());
+        Ok(())
+    }
+
+    #[test]
+    fn telemetry_should_be_overridable_by_env() -> Result<()> {
+        let config = AppConfig::from_layers(
+            [app_yml()],
+            vars(&[
+                ("CHAT__TELEMETRY__LOG_FORMAT", "json"),
+                ("CHAT__TELEMETRY__OTLP_ENDPOINT", "http://localhost:4317"),
+            ]),
+        )?;
+        assert_eq!(config.telemetry.log_format, chat_core::LogFormat::Json);
+        assert_eq!(
+            config.telemetry.otlp_endpoint.as_deref(),
+            Some("http://localhost:4317")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn missing_or_invalid_key_should_be_reported() {
+        let mut file = app_yml();
+        file["server"].as_mapping_mut().unwrap().remove("db_url");
+        let err = AppConfig::from_layers([file], vec![]).unwrap_err();
+        assert!(err.to_string().contains("server.db_url"));
+
+        let err = AppConfig::from_layers([app_yml()], vars(&[("CHAT__SERVER__PORT", "abc")]))
+            .unwrap_err();
+        assert!(err.to_string().contains("server.port"));
+
+        let err =
+            AppConfig::from_layers([app_yml()], vars(&[("CHAT__AUTH__SK", "secret")])).unwrap_err();
+        assert!(err.to_string().contains("auth.sk"));
+    }
+
+    #[test]
+    fn redacted_should_hide_secrets() -> Result<()> {
+        let config = AppConfig::from_layers([app_yml()], vec![])?.redacted();
+        assert_eq!(config.auth.sk, REDACTED);
+        assert_eq!(
+            config.server.db_url,
+            "postgres://postgres:<redacted>@localhost:5432/chat"
+        );
+        assert!(config.auth.pk.contains("PUBLIC KEY"));
+        Ok(())
     }
 }
@@ -1,4 +1,11 @@
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chat_core::ErrorOutput;
+use std::time::Duration;
 use thiserror::Error;
+use tracing::error;
+
 #[derive(Error, Debug)]
 pub enum AppError {
     #[error("email already exists: {0}")]
@@ -13,7 +20,159 @@ pub enum AppError {
     #[error("jwt error: {0}")]
     JwtError(#[from] jwt_simple::Error),
 
-
     #[error("http header parse error: {0}")]
     HttpHeaderError(#[from] axum::http::header::InvalidHeaderValue),
-}
\ No newline at end of file
+
+    #[error("not found: {0}")]
+    NotFound(String),
+
+    #[error("unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("multipart error: {0}")]
+    MultipartError(#[from] axum::extract::multipart::MultipartError),
+
+    #[error("too many requests, retry after {}s", retry_after_secs(.0))]
+    TooManyRequests(Duration),
+
+    #[error("internal error: {0}")]
+    Internal(String),
+}
+
+/// Retry-After 按整秒向上取整，至少为 1 秒
+fn retry_after_secs(duration: &Duration) -> u64 {
+    duration.as_secs_f64().ceil().max(1.0) as u64
+}
+
+impl AppError {
+    /// 错误对应的 HTTP 状态码
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::EmailAlreadyExists(_) => StatusCode::CONFLICT,
+            AppError::SqlxError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::PasswordHashError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::JwtError(_) => StatusCode::UNAUTHORIZED,
+            AppError::HttpHeaderError(_) => StatusCode::BAD_REQUEST,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            AppError::IoError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::MultipartError(e) => e.status(),
+            AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// 供客户端识别的错误码
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::EmailAlreadyExists(_) => "email_already_exists",
+            AppError::SqlxError(_) => "sql_error",
+            AppError::PasswordHashError(_) => "password_hash_error",
+            AppError::JwtError(_) => "jwt_error",
+            AppError::HttpHeaderError(_) => "http_header_error",
+            AppError::NotFound(_) => "not_found",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::InvalidInput(_) => "invalid_input",
+            AppError::IoError(_) => "io_error",
+            AppError::MultipartError(_) => "multipart_error",
+            AppError::TooManyRequests(_) => "too_many_requests",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    /// 5xx 错误只在日志中记录详情，响应中返回统一的提示，避免泄露 SQL、文件路径等内部信息
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let body = if status.is_server_error() {
+            error!(error = %self, code = self.code(), "internal server error");
+            ErrorOutput::new("internal server error", "internal_error")
+        } else {
+            ErrorOutput::new(self.to_string(), self.code())
+        };
+        let mut res = (status, Json(body)).into_response();
+        if let AppError::TooManyRequests(retry_after) = &self {
+            res.headers_mut()
+                .insert(header::RETRY_AFTER, retry_after_secs(retry_after).into());
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use axum::body::to_bytes;
+
+    #[tokio::test]
+    async fn app_error_should_render_json_envelope() -> Result<()> {
+        let res = AppError::EmailAlreadyExists("a@b.com".to_string()).into_response();
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        let body = to_bytes(res.into_body(), usize::MAX).await?;
+        let ret: ErrorOutput = serde_json::from_slice(&body)?;
+        assert_eq!(
+            ret,
+            ErrorOutput::new("email already exists: a@b.com", "email_already_exists")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn server_error_should_hide_details() -> Result<()> {
+        let err = AppError::IoError(std::io::Error::other("/tmp/chat/1/secret.png"));
+        let res = err.into_response();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = to_bytes(res.into_body(), usize::MAX).await?;
+        let ret: ErrorOutput = serde_json::from_slice(&body)?;
+        assert_eq!(
+            ret,
+            ErrorOutput::new("internal server error", "internal_error")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn too_many_requests_should_set_retry_after() {
+        let res = AppError::TooManyRequests(Duration::from_millis(1500)).into_response();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()[header::RETRY_AFTER], "2");
+    }
+
+    #[test]
+    fn app_error_status_should_match_variant() {
+        assert_eq!(
+            AppError::SqlxError(sqlx::Error::RowNotFound).status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        assert_eq!(
+            AppError::NotFound("chat 1".to_string()).status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            AppError::Unauthorized("no token".to_string()).status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            AppError::Forbidden("not a member".to_string()).status(),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            AppError::InvalidInput("empty name".to_string()).status(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+}
@@ -1,9 +1,269 @@
+use crate::models::{CreateUser, RefreshToken, SignInUser, User};
+use crate::{AppError, AppState};
+use axum::extract::State;
+use axum::http::StatusCode;
 use axum::response::IntoResponse;
+use axum::{Extension, Json};
+use chat_core::utils::TokenClaims;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
-pub(crate) async fn signin_handler() -> impl IntoResponse {
-    "signin"
+/// `token` 为短期有效的 access token，过期后用 `refresh_token` 调用 /api/refresh 换取新的 token
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthOutput {
+    pub token: String,
+    pub refresh_token: String,
 }
 
-pub(crate) async fn signup_handler() -> impl IntoResponse {
-    "signup"
-}
\ No newline at end of file
+pub(crate) async fn signin_handler(
+    State(state): State<AppState>,
+    Json(input): Json<SignInUser>,
+) -> Result<impl IntoResponse, AppError> {
+    // 同一邮箱连续登录失败过多时暂时锁定，锁定期间不再校验密码
+    let key = format!("signin:{}", input.email.to_lowercase());
+    if let Some(retry_after) = state.limiter.locked_for(&key) {
+        return Err(AppError::TooManyRequests(retry_after));
+    }
+    let user = state.verify_user(&input).await?;
+    match user {
+        Some(user) => {
+            state.limiter.clear_failures(&key);
+            let output = issue_tokens(&state, user).await?;
+            Ok((StatusCode::OK, Json(output)).into_response())
+        }
+        None => {
+            let config = &state.config.rate_limit;
+            let lockout = Duration::from_secs(config.lockout_secs);
+            match state
+                .limiter
+                .record_failure(&key, config.max_failed_signins, lockout)
+            {
+                Some(retry_after) => Err(AppError::TooManyRequests(retry_after)),
+                None => Err(AppError::Forbidden("invalid email or password".to_string())),
+            }
+        }
+    }
+}
+
+pub(crate) async fn signup_handler(
+    State(state): State<AppState>,
+    Json(input): Json<CreateUser>,
+) -> Result<impl IntoResponse, AppError> {
+    let user = state.create_user(input).await?;
+    let output = issue_tokens(&state, user).await?;
+    Ok((StatusCode::CREATED, Json(output)))
+}
+
+pub(crate) async fn refresh_handler(
+    State(state): State<AppState>,
+    Json(input): Json<RefreshToken>,
+) -> Result<impl IntoResponse, AppError> {
+    let (user, refresh_token) = state.rotate_refresh_token(&input.refresh_token).await?;
+    let token = state.ek.sign(user)?;
+    Ok(Json(AuthOutput {
+        token,
+        refresh_token,
+    }))
+}
+
+pub(crate) async fn signout_handler(
+    Extension(claims): Extension<TokenClaims>,
+    State(state): State<AppState>,
+    Json(input): Json<RefreshToken>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .revoke_refresh_token(claims.user.id, &input.refresh_token)
+        .await?;
+    state.revoke_access_token(&claims).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// 公开当前有效的校验公钥，供其他服务（如 notify_server）校验 token
+pub(crate) async fn jwks_handler(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.dk.jwks())
+}
+
+async fn issue_tokens(state: &AppState, user: User) -> Result<AuthOutput, AppError> {
+    let refresh_token = state.create_refresh_token(user.id).await?;
+    let token = state.ek.sign(user)?;
+    Ok(AuthOutput {
+        token,
+        refresh_token,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use axum::body::to_bytes;
+    use chat_core::utils::Jwks;
+
+    fn create_user_input(email: &str, password: &str) -> CreateUser {
+        CreateUser {
+            full_name: "Auth Test".to_string(),
+            email: email.to_string(),
+            workspace: "test_workspace".to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn signup_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let email = "signup_handler@example.com";
+        let input = create_user_input(email, "password123");
+        let ret = signup_handler(State(state.clone()), Json(input))
+            .await?
+            .into_response();
+        assert_eq!(ret.status(), StatusCode::CREATED);
+        let body = to_bytes(ret.into_body(), usize::MAX).await?;
+        let ret: AuthOutput = serde_json::from_slice(&body)?;
+        assert_ne!(ret.token, "");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn signup_duplicate_email_should_409() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let email = "signup_duplicate@example.com";
+        let input = create_user_input(email, "password123");
+        signup_handler(State(state.clone()), Json(input.clone())).await?;
+        let ret = signup_handler(State(state.clone()), Json(input))
+            .await
+            .into_response();
+        assert_eq!(ret.status(), StatusCode::CONFLICT);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn signin_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let email = "signin_handler@example.com";
+        state
+            .create_user(create_user_input(email, "password123"))
+            .await?;
+
+        let input = SignInUser {
+            email: email.to_string(),
+            password: "password123".to_string(),
+        };
+        let ret = signin_handler(State(state.clone()), Json(input))
+            .await?
+            .into_response();
+        assert_eq!(ret.status(), StatusCode::OK);
+        let body = to_bytes(ret.into_body(), usize::MAX).await?;
+        let ret: AuthOutput = serde_json::from_slice(&body)?;
+        assert_ne!(ret.token, "");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn signin_with_wrong_password_should_403() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let email = "signin_wrong_password@example.com";
+        state
+            .create_user(create_user_input(email, "password123"))
+            .await?;
+
+        let input = SignInUser {
+            email: email.to_string(),
+            password: "wrong_password".to_string(),
+        };
+        let ret = signin_handler(State(state.clone()), Json(input))
+            .await
+            .into_response();
+        assert_eq!(ret.status(), StatusCode::FORBIDDEN);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refresh_and_signout_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let email = "refresh_handler@example.com";
+        let input = create_user_input(email, "password123");
+        let ret = signup_handler(State(state.clone()), Json(input))
+            .await?
+            .into_response();
+        let body = to_bytes(ret.into_body(), usize::MAX).await?;
+        let output: AuthOutput = serde_json::from_slice(&body)?;
+
+        let input = RefreshToken {
+            refresh_token: output.refresh_token.clone(),
+        };
+        let ret = refresh_handler(State(state.clone()), Json(input))
+            .await?
+            .into_response();
+        assert_eq!(ret.status(), StatusCode::OK);
+        let body = to_bytes(ret.into_body(), usize::MAX).await?;
+        let refreshed: AuthOutput = serde_json::from_slice(&body)?;
+        assert_ne!(refreshed.refresh_token, output.refresh_token);
+
+        // 旧的 refresh token 已经失效
+        let input = RefreshToken {
+            refresh_token: output.refresh_token,
+        };
+        let ret = refresh_handler(State(state.clone()), Json(input))
+            .await
+            .into_response();
+        assert_eq!(ret.status(), StatusCode::UNAUTHORIZED);
+
+        // 退出登录后 access token 被吊销
+        let claims = state.dk.decode(&refreshed.token)?;
+        let input = RefreshToken {
+            refresh_token: refreshed.refresh_token,
+        };
+        let ret = signout_handler(Extension(claims.clone()), State(state.clone()), Json(input))
+            .await?
+            .into_response();
+        assert_eq!(ret.status(), StatusCode::NO_CONTENT);
+        assert!(state.is_token_revoked(&claims.jti).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn jwks_should_contain_signing_key() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let ret = jwks_handler(State(state.clone())).await.into_response();
+        assert_eq!(ret.status(), StatusCode::OK);
+        let body = to_bytes(ret.into_body(), usize::MAX).await?;
+        let jwks: Jwks = serde_json::from_slice(&body)?;
+        assert!(jwks.keys.iter().any(|k| k.kid == state.ek.kid()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn signin_should_lock_out_after_failures() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let email = "signin_lockout@example.com";
+        state
+            .create_user(create_user_input(email, "password123"))
+            .await?;
+
+        let signin = |password: &str| {
+            let input = SignInUser {
+                email: email.to_string(),
+                password: password.to_string(),
+            };
+            signin_handler(State(state.clone()), Json(input))
+        };
+        let max = state.config.rate_limit.max_failed_signins;
+        for _ in 1..max {
+            let ret = signin("wrong").await.into_response();
+            assert_eq!(ret.status(), StatusCode::FORBIDDEN);
+        }
+        let ret = signin("wrong").await.into_response();
+        assert_eq!(ret.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(ret.headers().contains_key(axum::http::header::RETRY_AFTER));
+
+        // 锁定期间正确的密码也被拒绝
+        let ret = signin("password123").await.into_response();
+        assert_eq!(ret.status(), StatusCode::TOO_MANY_REQUESTS);
+        Ok(())
+    }
+}
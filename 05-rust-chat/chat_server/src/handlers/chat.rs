@@ -1,17 +1,121 @@
+use crate::models::{ChatMembers, CreateChat, CreateInvitation, UpdateChat, UpdateMemberRole};
+use crate::{AppError, AppState};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
 use axum::response::IntoResponse;
+use axum::Json;
+use chat_core::middlewares::CurrentUser;
 
-pub(crate) async fn list_chat_handler() -> impl IntoResponse {
-    "chat"
+pub(crate) async fn list_chat_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let chats = state.fetch_chats(user.id).await?;
+    Ok(Json(chats))
 }
 
-pub(crate) async fn create_chat_handler() -> impl IntoResponse {
-    "create chat"
+pub(crate) async fn create_chat_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Json(input): Json<CreateChat>,
+) -> Result<impl IntoResponse, AppError> {
+    let chat = state.create_chat(input, user.id).await?;
+    Ok((StatusCode::CREATED, Json(chat)))
 }
 
-pub(crate) async fn update_chat_handler() -> impl IntoResponse {
-    "update chat"
+pub(crate) async fn update_chat_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(input): Json<UpdateChat>,
+) -> Result<impl IntoResponse, AppError> {
+    let chat = state.update_chat(id, input, user.id).await?;
+    Ok(Json(chat))
 }
 
-pub(crate) async fn delete_chat_handler() -> impl IntoResponse {
-    "delete chat"
-}
\ No newline at end of file
+pub(crate) async fn delete_chat_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    state.delete_chat(id, user.id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) async fn typing_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    state.send_typing(id, user.id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) async fn list_members_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    let members = state.fetch_chat_members(id, user.id).await?;
+    Ok(Json(members))
+}
+
+pub(crate) async fn add_members_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(input): Json<ChatMembers>,
+) -> Result<impl IntoResponse, AppError> {
+    let chat = state.add_chat_members(id, input, user.id).await?;
+    Ok(Json(chat))
+}
+
+pub(crate) async fn remove_members_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(input): Json<ChatMembers>,
+) -> Result<impl IntoResponse, AppError> {
+    let chat = state.remove_chat_members(id, input, user.id).await?;
+    Ok(Json(chat))
+}
+
+pub(crate) async fn update_member_role_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Path((id, member_id)): Path<(i64, i64)>,
+    Json(input): Json<UpdateMemberRole>,
+) -> Result<impl IntoResponse, AppError> {
+    let chat = state
+        .update_member_role(id, member_id, input, user.id)
+        .await?;
+    Ok(Json(chat))
+}
+
+pub(crate) async fn leave_chat_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    state.leave_chat(id, user.id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) async fn create_invitation_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(input): Json<CreateInvitation>,
+) -> Result<impl IntoResponse, AppError> {
+    let invitation = state.create_invitation(id, input, user.id).await?;
+    Ok((StatusCode::CREATED, Json(invitation)))
+}
+
+pub(crate) async fn accept_invitation_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let chat = state.accept_invitation(&code, &user).await?;
+    Ok(Json(chat))
+}
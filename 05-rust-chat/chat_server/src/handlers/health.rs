@@ -0,0 +1,95 @@
+use crate::AppState;
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::IntoResponse;
+use chat_core::health::Readiness;
+use chat_core::metrics::{labels, write_metric, METRICS_CONTENT_TYPE};
+use std::time::Duration;
+
+/// 就绪检查中数据库探活的超时时间
+const DB_PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 就绪检查，数据库可用时返回 200，否则返回 503
+pub(crate) async fn readyz_handler(State(state): State<AppState>) -> impl IntoResponse {
+    let ping = sqlx::query("SELECT 1").execute(&state.pool);
+    let database = match tokio::time::timeout(DB_PING_TIMEOUT, ping).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err("ping timed out".to_string()),
+    };
+    Readiness::new([("database", database)])
+}
+
+/// Prometheus 指标：各路由的请求数与耗时，以及数据库连接池的使用情况
+pub(crate) async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    let mut out = String::new();
+    state.metrics.encode(&mut out);
+
+    let size = state.pool.size() as usize;
+    let idle = state.pool.num_idle();
+    write_metric(
+        &mut out,
+        "db_pool_connections",
+        "gauge",
+        "Number of database pool connections by state.",
+        [
+            (labels(&[("state", "idle")]), idle as f64),
+            (
+                labels(&[("state", "in_use")]),
+                size.saturating_sub(idle) as f64,
+            ),
+        ],
+    );
+    write_metric(
+        &mut out,
+        "db_pool_max_connections",
+        "gauge",
+        "Maximum number of database pool connections.",
+        [(
+            String::new(),
+            state.pool.options().get_max_connections() as f64,
+        )],
+    );
+    ([(CONTENT_TYPE, METRICS_CONTENT_TYPE)], out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use axum::body::to_bytes;
+    use axum::http::StatusCode;
+
+    #[tokio::test]
+    async fn readyz_should_ping_database() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let res = readyz_handler(State(state.clone())).await.into_response();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = to_bytes(res.into_body(), usize::MAX).await?;
+        let readiness: Readiness = serde_json::from_slice(&body)?;
+        assert_eq!(readiness.checks["database"], "ok");
+
+        state.pool.close().await;
+        let res = readyz_handler(State(state)).await.into_response();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn metrics_should_include_requests_and_pool() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        state
+            .metrics
+            .observe("GET", "/api/chat", 200, Duration::from_millis(5));
+        let res = metrics_handler(State(state)).await.into_response();
+        assert_eq!(res.headers()[CONTENT_TYPE], METRICS_CONTENT_TYPE);
+        let body = to_bytes(res.into_body(), usize::MAX).await?;
+        let body = String::from_utf8(body.to_vec())?;
+        assert!(
+            body.contains(r#"http_requests_total{method="GET",route="/api/chat",status="200"} 1"#)
+        );
+        assert!(body.contains(r#"db_pool_connections{state="idle"}"#));
+        assert!(body.contains("db_pool_max_connections "));
+        Ok(())
+    }
+}
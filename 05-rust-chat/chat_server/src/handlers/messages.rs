@@ -1,9 +1,209 @@
+use crate::models::{
+    ChatFile, CreateMessage, CreateReaction, ListMessages, MarkRead, SearchMessages, UpdateMessage,
+};
+use crate::{AppError, AppState};
+use axum::extract::{Multipart, Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
 use axum::response::IntoResponse;
+use axum::Json;
+use chat_core::middlewares::CurrentUser;
+use tokio::fs;
+use tracing::{info, warn};
 
-pub(crate) async fn send_message_handler() -> impl IntoResponse {
-    "send message"
+pub(crate) async fn send_message_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(input): Json<CreateMessage>,
+) -> Result<impl IntoResponse, AppError> {
+    let message = state.create_message(input, id, user.id).await?;
+    Ok((StatusCode::CREATED, Json(message)))
 }
 
-pub(crate) async fn list_message_handler() -> impl IntoResponse {
-    "list message"
-}
\ No newline at end of file
+pub(crate) async fn list_message_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Query(input): Query<ListMessages>,
+) -> Result<impl IntoResponse, AppError> {
+    let messages = state.list_messages(input, id, user.id).await?;
+    Ok(Json(messages))
+}
+
+pub(crate) async fn search_message_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Query(input): Query<SearchMessages>,
+) -> Result<impl IntoResponse, AppError> {
+    let hits = state.search_messages(input, user.id).await?;
+    Ok(Json(hits))
+}
+
+pub(crate) async fn update_message_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Path((id, mid)): Path<(i64, i64)>,
+    Json(input): Json<UpdateMessage>,
+) -> Result<impl IntoResponse, AppError> {
+    let message = state.update_message(input, id, mid, user.id).await?;
+    Ok(Json(message))
+}
+
+pub(crate) async fn delete_message_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Path((id, mid)): Path<(i64, i64)>,
+) -> Result<impl IntoResponse, AppError> {
+    state.delete_message(id, mid, user.id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) async fn list_reaction_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Path((id, mid)): Path<(i64, i64)>,
+) -> Result<impl IntoResponse, AppError> {
+    let reactions = state.list_reactions(id, mid, user.id).await?;
+    Ok(Json(reactions))
+}
+
+pub(crate) async fn add_reaction_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Path((id, mid)): Path<(i64, i64)>,
+    Json(input): Json<CreateReaction>,
+) -> Result<impl IntoResponse, AppError> {
+    let reaction = state.add_reaction(input, id, mid, user.id).await?;
+    Ok((StatusCode::CREATED, Json(reaction)))
+}
+
+pub(crate) async fn remove_reaction_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Path((id, mid, emoji)): Path<(i64, i64, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    state.remove_reaction(id, mid, &emoji, user.id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) async fn mark_read_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(input): Json<MarkRead>,
+) -> Result<impl IntoResponse, AppError> {
+    let read = state.mark_read(input, id, user.id).await?;
+    Ok(Json(read))
+}
+
+pub(crate) async fn upload_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, AppError> {
+    let base_dir = &state.config.server.base_dir;
+    let mut files = vec![];
+    while let Some(field) = multipart.next_field().await? {
+        let Some(filename) = field.file_name().map(|name| name.to_string()) else {
+            warn!("skip multipart field without filename");
+            continue;
+        };
+        let data = field.bytes().await?;
+
+        let file = ChatFile::new(user.ws_id, &filename, &data);
+        let path = file.path(base_dir);
+        if path.exists() {
+            info!("file {} already exists: {:?}", filename, path);
+        } else {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            fs::write(&path, &data).await?;
+        }
+        files.push(file.url());
+    }
+    Ok(Json(files))
+}
+
+pub(crate) async fn file_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Path((ws_id, path)): Path<(i64, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    // 其他工作空间的文件与不存在的文件一样返回 NotFound，避免泄露文件是否存在
+    if user.ws_id != ws_id {
+        return Err(AppError::NotFound(format!("file {}/{}", ws_id, path)));
+    }
+
+    // 通过解析校验路径格式，拒绝 `..` 之类的路径穿越
+    let file: ChatFile = format!("/api/files/{}/{}", ws_id, path).parse()?;
+    let path = file.path(&state.config.server.base_dir);
+    if !path.exists() {
+        return Err(AppError::NotFound(format!("file {}", file.url())));
+    }
+
+    let mime = mime_guess::from_path(&path).first_or_octet_stream();
+    let body = fs::read(&path).await?;
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, mime.to_string().parse()?);
+    Ok((headers, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::User;
+    use anyhow::Result;
+
+    fn test_user(ws_id: i64) -> User {
+        User {
+            id: 1,
+            ws_id,
+            fullname: "zhangSan".to_string(),
+            email: "test@mail.com".to_string(),
+            password_hash: None,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn file_handler_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let file = ChatFile::new(1, "hello.txt", b"hello world");
+        let path = file.path(&state.config.server.base_dir);
+        fs::create_dir_all(path.parent().unwrap()).await?;
+        fs::write(&path, b"hello world").await?;
+
+        let url_path = file.url().trim_start_matches("/api/files/1/").to_string();
+        let ret = file_handler(
+            CurrentUser(test_user(1)),
+            State(state.clone()),
+            Path((1, url_path.clone())),
+        )
+        .await?
+        .into_response();
+        assert_eq!(ret.status(), StatusCode::OK);
+        assert_eq!(ret.headers()[header::CONTENT_TYPE], "text/plain");
+
+        // 其他工作空间的用户看不到该文件
+        let ret = file_handler(
+            CurrentUser(test_user(2)),
+            State(state.clone()),
+            Path((1, url_path)),
+        )
+        .await
+        .into_response();
+        assert_eq!(ret.status(), StatusCode::NOT_FOUND);
+
+        // 非法路径
+        let ret = file_handler(
+            CurrentUser(test_user(1)),
+            State(state),
+            Path((1, "../../etc/passwd".to_string())),
+        )
+        .await
+        .into_response();
+        assert_eq!(ret.status(), StatusCode::BAD_REQUEST);
+        Ok(())
+    }
+}
@@ -1,12 +1,16 @@
 mod chat;
 mod messages;
 mod auth;
+mod health;
+mod workspace;
 
 use axum::response::IntoResponse;
 
 pub(crate) use chat::*;
 pub(crate) use messages::*;
 pub(crate) use auth::*;
+pub(crate) use health::*;
+pub(crate) use workspace::*;
 
 pub(crate) async fn index_handler() -> impl IntoResponse {
     "index"
@@ -0,0 +1,13 @@
+use crate::{AppError, AppState};
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::Json;
+use chat_core::middlewares::CurrentUser;
+
+pub(crate) async fn list_chat_users_handler(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let users = state.fetch_chat_users(user.ws_id).await?;
+    Ok(Json(users))
+}
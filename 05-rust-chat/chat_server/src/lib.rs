@@ -2,29 +2,43 @@ mod config;
 mod handlers;
 mod models;
 mod error;
-
-mod utils;
+mod rate_limit;
 
 use handlers::*;
 
-use std::{ops::Deref, sync::Arc};
+use std::{fmt, ops::Deref, sync::Arc};
 
 use axum::{
-    routing::{get, patch, post},
+    async_trait,
+    handler::Handler,
+    middleware::from_fn_with_state,
+    routing::{delete, get, patch, post},
     Router,
 };
+use chat_core::health::healthz_handler;
+use chat_core::metrics::{track_metrics, HttpMetrics};
+use chat_core::middlewares::{set_layer, verify_token, TokenVerify};
+use chat_core::utils::{DecodingKey, EncodingKey, TokenClaims};
+use rate_limit::{
+    ip_rate_limit, spawn_evict_expired, user_rate_limit, MemoryStore, RateLimitStore,
+};
+use sqlx::PgPool;
 
 pub use config::AppConfig;
+pub use error::AppError;
 
 #[derive(Debug, Clone)]
 pub(crate) struct AppState {
     inner: Arc<AppStateInner>,
 }
 
-#[derive(Debug)]
 pub(crate) struct AppStateInner {
-    #[allow(dead_code)]
     pub(crate) config: AppConfig,
+    pub(crate) ek: EncodingKey,
+    pub(crate) dk: DecodingKey,
+    pub(crate) pool: PgPool,
+    pub(crate) limiter: Arc<dyn RateLimitStore>,
+    pub(crate) metrics: Arc<HttpMetrics>,
 }
 
 // 当我调用 state.config => state.inner.config
@@ -36,31 +50,149 @@ impl Deref for AppState {
     }
 }
 
+impl fmt::Debug for AppStateInner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppStateInner")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
 impl AppState {
-    pub fn new(config: AppConfig) -> Self {
-        Self {
-            inner: Arc::new(AppStateInner { config }),
+    pub async fn try_new(config: AppConfig) -> Result<Self, AppError> {
+        let ek = EncodingKey::load(&config.auth.sk)?;
+        let dk = DecodingKey::load_all(
+            std::iter::once(&config.auth.pk)
+                .chain(&config.auth.previous_pks)
+                .map(String::as_str),
+        )?;
+        let pool = PgPool::connect(&config.server.db_url).await?;
+        let limiter: Arc<dyn RateLimitStore> = Arc::new(MemoryStore::default());
+        spawn_evict_expired(&limiter);
+        Ok(Self {
+            inner: Arc::new(AppStateInner {
+                config,
+                ek,
+                dk,
+                pool,
+                limiter,
+                metrics: Arc::new(HttpMetrics::default()),
+            }),
+        })
+    }
+}
+
+#[async_trait]
+impl TokenVerify for AppState {
+    type Error = AppError;
+
+    async fn verify(&self, token: &str) -> Result<TokenClaims, Self::Error> {
+        let claims = self.dk.decode(token)?;
+        if self.is_token_revoked(&claims.jti).await? {
+            return Err(AppError::Unauthorized("token has been revoked".to_string()));
         }
+        Ok(claims)
     }
 }
 
-pub fn get_router(config: AppConfig) -> Router {
-    let state = AppState::new(config);
+pub async fn get_router(config: AppConfig) -> Result<Router, AppError> {
+    let state = AppState::try_new(config).await?;
 
     let api = Router::new()
-        .route("/signin", post(signin_handler))
-        .route("/signup", post(signup_handler))
+        .route("/users", get(list_chat_users_handler))
         .route("/chat", get(list_chat_handler).post(create_chat_handler))
         .route(
             "/chat/:id",
             patch(update_chat_handler)
                 .delete(delete_chat_handler)
-                .post(send_message_handler),
+                .post(
+                    send_message_handler
+                        .layer(from_fn_with_state(state.clone(), user_rate_limit)),
+                ),
+        )
+        .route("/chat/:id/messages", get(list_message_handler))
+        .route(
+            "/chat/:id/messages/:mid",
+            patch(update_message_handler).delete(delete_message_handler),
+        )
+        .route(
+            "/chat/:id/messages/:mid/reactions",
+            get(list_reaction_handler).post(add_reaction_handler),
         )
-        .route("/chat/:id/messages", get(list_message_handler));
+        .route(
+            "/chat/:id/messages/:mid/reactions/:emoji",
+            delete(remove_reaction_handler),
+        )
+        .route(
+            "/chat/:id/members",
+            get(list_members_handler)
+                .post(add_members_handler)
+                .delete(remove_members_handler),
+        )
+        .route(
+            "/chat/:id/members/:member_id",
+            patch(update_member_role_handler),
+        )
+        .route("/chat/:id/leave", post(leave_chat_handler))
+        .route("/chat/:id/invitations", post(create_invitation_handler))
+        .route("/invitations/:code", post(accept_invitation_handler))
+        .route("/chat/:id/read", post(mark_read_handler))
+        .route("/chat/:id/typing", post(typing_handler))
+        .route("/search", get(search_message_handler))
+        .route("/upload", post(upload_handler))
+        .route("/files/:ws_id/*path", get(file_handler))
+        .route("/signout", post(signout_handler))
+        .layer(from_fn_with_state(state.clone(), verify_token::<AppState>))
+        // 登录、注册、刷新 token 接口不需要鉴权，按 IP 限流
+        .merge(
+            Router::new()
+                .route("/signin", post(signin_handler))
+                .route("/signup", post(signup_handler))
+                .route("/refresh", post(refresh_handler))
+                .layer(from_fn_with_state(state.clone(), ip_rate_limit)),
+        );
 
-    Router::new()
+    let app = Router::new()
         .route("/", get(index_handler))
+        .route("/.well-known/jwks.json", get(jwks_handler))
         .nest("/api", api)
-        .with_state(state)
+        .route_layer(from_fn_with_state(state.metrics.clone(), track_metrics));
+    let app = set_layer(app);
+
+    // 探针与指标接口不计入请求统计，也不输出请求日志
+    let probes = Router::new()
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler))
+        .route("/metrics", get(metrics_handler));
+
+    Ok(app.merge(probes).with_state(state))
+}
+
+#[cfg(test)]
+impl AppState {
+    /// 构造测试用的 AppState
+    /// 在 .env 中 DATABASE_URL 指向的 Postgres 上创建独立的临时数据库，
+    /// 返回的 TestPg 离开作用域时删除该数据库
+    pub async fn new_for_test() -> Result<(chat_core::test_util::TestPg, Self), AppError> {
+        dotenvy::dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let tdb = chat_core::test_util::TestPg::new(&database_url).await?;
+        let db_url = tdb.url();
+        let config = AppConfig {
+            server: config::ServerConfig {
+                port: 0,
+                db_url,
+                base_dir: std::env::temp_dir().join("chat_server_test"),
+            },
+            auth: config::AuthConfig {
+                sk: include_str!("../../chat_core/fixtures/encoding.pem").to_string(),
+                pk: include_str!("../../chat_core/fixtures/decoding.pem").to_string(),
+                previous_pks: vec![],
+            },
+            rate_limit: config::RateLimitConfig::default(),
+            telemetry: chat_core::TelemetryConfig::default(),
+        };
+        let state = Self::try_new(config).await?;
+        Ok((tdb, state))
+    }
 }
\ No newline at end of file
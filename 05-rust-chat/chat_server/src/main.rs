@@ -1,25 +1,30 @@
 use chat_server::{get_router, AppConfig};
 use anyhow::Result;
+use std::net::SocketAddr;
 use tokio::net::TcpListener;
-use tracing::{info, level_filters::LevelFilter};
-use tracing_subscriber::{fmt::Layer, layer::SubscriberExt, util::SubscriberInitExt, Layer as _};
-
-mod config;
-
+use tracing::info;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let layer = Layer::new().with_filter(LevelFilter::INFO);
-    tracing_subscriber::registry().with(layer).init();
-
     let config = AppConfig::load()?;
+    let _guard = chat_core::init_tracing("chat-server", &config.telemetry)?;
+    // --check-config: 只校验并输出最终生效的配置（隐去敏感信息），不启动服务
+    if std::env::args().any(|arg| arg == "--check-config") {
+        print!("{}", serde_yaml::to_string(&config.redacted())?);
+        return Ok(());
+    }
     let addr = format!("0.0.0.0:{}", config.server.port);
 
-    let app = get_router(config);
+    let app = get_router(config).await?;
     let listener = TcpListener::bind(&addr).await?;
     info!("Listening on: {}", addr);
 
-    axum::serve(listener, app.into_make_service()).await?;
+    // 按 IP 限流需要客户端地址
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
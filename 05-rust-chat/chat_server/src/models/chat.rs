@@ -0,0 +1,564 @@
+use crate::error::AppError;
+use crate::models::{Chat, ChatType};
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection, PgPool};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateChat {
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub r#type: ChatType,
+    pub members: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateChat {
+    pub name: Option<String>,
+    pub members: Option<Vec<i64>>,
+}
+
+/// 聊天列表项，附带当前用户的已读位置和未读消息数（不含自己发送的和已删除的消息）
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct ChatWithUnread {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub chat: Chat,
+    pub last_read_message_id: Option<i64>,
+    pub unread_count: i64,
+}
+
+impl AppState {
+    /// 新建聊天
+    /// 按聊天类型校验成员后创建聊天，频道的创建者即为频道所有者
+    ///
+    /// # 参数
+    /// * `input` - 包含聊天信息的CreateChat结构体
+    /// * `user_id` - 创建者ID
+    ///
+    /// # 返回
+    /// * `Result<Chat, AppError>` - 成功则返回新创建的聊天实例，失败则返回错误
+    pub async fn create_chat(&self, input: CreateChat, user_id: i64) -> Result<Chat, AppError> {
+        let mut members = input.members;
+        let owner_id = match input.r#type {
+            ChatType::PrivateChannel | ChatType::PublicChannel => {
+                if !members.contains(&user_id) {
+                    members.push(user_id);
+                }
+                Some(user_id)
+            }
+            ChatType::Single | ChatType::Group => {
+                if !members.contains(&user_id) {
+                    return Err(AppError::InvalidInput(
+                        "creator must be a member of the chat".to_string(),
+                    ));
+                }
+                None
+            }
+        };
+        validate_chat(
+            input.r#type,
+            input.name.as_deref(),
+            &members,
+            user_id,
+            &self.pool,
+        )
+        .await?;
+
+        let mut tx = self.pool.begin().await?;
+        let (id,): (i64,) = sqlx::query_as(
+            r#"
+            INSERT INTO chats (name, type, owner_id)
+            VALUES ($1, $2, $3)
+            RETURNING id
+            "#,
+        )
+        .bind(&input.name)
+        .bind(input.r#type)
+        .bind(owner_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        sync_chat_members(&mut tx, id, &members, owner_id).await?;
+        tx.commit().await?;
+        self.get_chat_by_id(id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("chat {}", id)))
+    }
+
+    /// 查询用户的聊天列表
+    /// 只返回当前用户所在的聊天，并附带每个聊天的未读消息数
+    ///
+    /// # 参数
+    /// * `user_id` - 用户ID
+    ///
+    /// # 返回
+    /// * `Result<Vec<ChatWithUnread>, AppError>` - 成功则返回聊天列表，失败则返回错误
+    pub async fn fetch_chats(&self, user_id: i64) -> Result<Vec<ChatWithUnread>, AppError> {
+        let chats = sqlx::query_as(
+            r#"
+            SELECT c.id, c.name, c.type, c.members, c.admins, c.owner_id, c.created_at,
+                   r.last_read_message_id,
+                   (SELECT count(*)
+                    FROM messages m
+                    WHERE m.chat_id = c.id
+                      AND m.sender_id <> $1
+                      AND m.id > COALESCE(r.last_read_message_id, 0)
+                      AND m.deleted_at IS NULL) AS unread_count
+            FROM chats c
+                     JOIN chat_members cm ON cm.chat_id = c.id AND cm.user_id = $1
+                     LEFT JOIN chat_reads r ON r.chat_id = c.id AND r.user_id = $1
+            ORDER BY c.id
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(chats)
+    }
+
+    /// 查找聊天
+    /// 根据聊天ID查找聊天
+    ///
+    /// # 参数
+    /// * `id` - 聊天ID
+    ///
+    /// # 返回
+    /// * `Result<Option<Chat>, AppError>` - 成功则返回可能存在的聊天实例，失败则返回错误
+    pub async fn get_chat_by_id(&self, id: i64) -> Result<Option<Chat>, AppError> {
+        let chat = sqlx::query_as(
+            r#"
+            SELECT id, name, type, members, admins, owner_id, created_at
+            FROM chats
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(chat)
+    }
+
+    /// 更新聊天
+    /// 频道只有所有者可以修改，单聊不允许修改成员，其余聊天成员均可修改
+    ///
+    /// # 参数
+    /// * `id` - 聊天ID
+    /// * `input` - 包含修改内容的UpdateChat结构体
+    /// * `user_id` - 操作者ID
+    ///
+    /// # 返回
+    /// * `Result<Chat, AppError>` - 成功则返回修改后的聊天实例，失败则返回错误
+    pub async fn update_chat(
+        &self,
+        id: i64,
+        input: UpdateChat,
+        user_id: i64,
+    ) -> Result<Chat, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let chat = lock_chat_for_member(&mut tx, id, user_id).await?;
+        ensure_can_manage(&chat, user_id)?;
+        if chat.r#type == ChatType::Single && input.members.is_some() {
+            return Err(AppError::InvalidInput(
+                "members of a single chat cannot be changed".to_string(),
+            ));
+        }
+
+        let name = input.name.or(chat.name);
+        let members = input.members.unwrap_or(chat.members);
+        if let Some(owner_id) = chat.owner_id.filter(|owner_id| !members.contains(owner_id)) {
+            return Err(AppError::InvalidInput(format!(
+                "the owner {} cannot be removed from the chat",
+                owner_id
+            )));
+        }
+        validate_chat(chat.r#type, name.as_deref(), &members, user_id, &self.pool).await?;
+
+        sqlx::query("UPDATE chats SET name = $1 WHERE id = $2")
+            .bind(&name)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sync_chat_members(&mut tx, id, &members, chat.owner_id).await?;
+        tx.commit().await?;
+        self.get_chat_by_id(id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("chat {}", id)))
+    }
+
+    /// 删除聊天
+    /// 同时删除聊天下的所有消息，权限规则与修改聊天一致
+    ///
+    /// # 参数
+    /// * `id` - 聊天ID
+    /// * `user_id` - 操作者ID
+    ///
+    /// # 返回
+    /// * `Result<(), AppError>` - 成功则返回空，失败则返回错误
+    pub async fn delete_chat(&self, id: i64, user_id: i64) -> Result<(), AppError> {
+        let chat = self.get_chat_for_member(id, user_id).await?;
+        ensure_can_manage(&chat, user_id)?;
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM messages WHERE chat_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM chats WHERE id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// 发送正在输入的信号
+    /// 通过 pg_notify 交给 notify_server 转发给其他成员，不写入数据库
+    ///
+    /// # 参数
+    /// * `id` - 聊天ID
+    /// * `user_id` - 正在输入的用户ID
+    ///
+    /// # 返回
+    /// * `Result<(), AppError>` - 成功则返回空，失败则返回错误
+    pub async fn send_typing(&self, id: i64, user_id: i64) -> Result<(), AppError> {
+        let chat = self.get_chat_for_member(id, user_id).await?;
+        let payload = serde_json::json!({
+            "chat_id": chat.id,
+            "user_id": user_id,
+            "members": chat.members,
+        });
+        sqlx::query("SELECT pg_notify('chat_typing', $1)")
+            .bind(payload.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// 查找聊天并确认用户是该聊天成员，不是成员时同样返回 NotFound，避免泄露聊天是否存在
+    pub(crate) async fn get_chat_for_member(
+        &self,
+        id: i64,
+        user_id: i64,
+    ) -> Result<Chat, AppError> {
+        match self.get_chat_by_id(id).await? {
+            Some(chat) if chat.members.contains(&user_id) => Ok(chat),
+            _ => Err(AppError::NotFound(format!("chat {}", id))),
+        }
+    }
+}
+
+/// 锁定聊天记录后读取聊天，同一聊天的成员变更因此在各自的事务中串行执行
+pub(super) async fn lock_chat(conn: &mut PgConnection, id: i64) -> Result<Option<Chat>, AppError> {
+    let chat = sqlx::query_as(
+        r#"
+        SELECT id, name, type, members, admins, owner_id, created_at
+        FROM chats
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await?;
+    Ok(chat)
+}
+
+/// 锁定聊天并确认用户是该聊天成员，不是成员时返回 NotFound
+pub(super) async fn lock_chat_for_member(
+    conn: &mut PgConnection,
+    id: i64,
+    user_id: i64,
+) -> Result<Chat, AppError> {
+    match lock_chat(conn, id).await? {
+        Some(chat) if chat.members.contains(&user_id) => Ok(chat),
+        _ => Err(AppError::NotFound(format!("chat {}", id))),
+    }
+}
+
+/// 将聊天成员同步为给定的列表
+/// 不在列表中的成员被移除，新成员以普通成员（所有者为 owner）身份加入，已有成员的角色保持不变；
+/// 需在锁定聊天的事务中调用，成员变化由数据库触发器通知 notify_server
+pub(super) async fn sync_chat_members(
+    conn: &mut PgConnection,
+    id: i64,
+    members: &[i64],
+    owner_id: Option<i64>,
+) -> Result<(), AppError> {
+    sqlx::query("DELETE FROM chat_members WHERE chat_id = $1 AND NOT (user_id = ANY($2))")
+        .bind(id)
+        .bind(members)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query(
+        r#"
+        INSERT INTO chat_members (chat_id, user_id, role)
+        SELECT $1, m.user_id,
+               CASE WHEN m.user_id = $3 THEN 'owner' ELSE 'member' END::chat_role
+        FROM unnest($2::bigint[]) AS m(user_id)
+        ON CONFLICT (chat_id, user_id) DO NOTHING
+        "#,
+    )
+    .bind(id)
+    .bind(members)
+    .bind(owner_id)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// 频道只有所有者可以管理，其余聊天没有所有者，成员均可管理
+fn ensure_can_manage(chat: &Chat, user_id: i64) -> Result<(), AppError> {
+    match chat.owner_id {
+        Some(owner_id) if owner_id != user_id => Err(AppError::Forbidden(format!(
+            "only the owner can manage chat {}",
+            chat.id
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// 按聊天类型校验名称与成员，并确认所有成员都是与操作者同一工作空间的已存在用户
+pub(super) async fn validate_chat(
+    chat_type: ChatType,
+    name: Option<&str>,
+    members: &[i64],
+    user_id: i64,
+    pool: &PgPool,
+) -> Result<(), AppError> {
+    let mut unique = members.to_vec();
+    unique.sort_unstable();
+    unique.dedup();
+    if unique.len() != members.len() {
+        return Err(AppError::InvalidInput(
+            "chat members must be unique".to_string(),
+        ));
+    }
+
+    match chat_type {
+        ChatType::Single if members.len() != 2 => {
+            return Err(AppError::InvalidInput(
+                "single chat must have exactly 2 members".to_string(),
+            ));
+        }
+        ChatType::Group if members.len() < 3 => {
+            return Err(AppError::InvalidInput(
+                "group chat must have at least 3 members".to_string(),
+            ));
+        }
+        ChatType::PrivateChannel | ChatType::PublicChannel
+            if name.unwrap_or_default().trim().is_empty() =>
+        {
+            return Err(AppError::InvalidInput(
+                "channel must have a name".to_string(),
+            ));
+        }
+        _ => {}
+    }
+
+    let (count,): (i64,) = sqlx::query_as(
+        r#"
+        SELECT count(*)
+        FROM users
+        WHERE id = ANY($1) AND ws_id = (SELECT ws_id FROM users WHERE id = $2)
+        "#,
+    )
+    .bind(members)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    if count != members.len() as i64 {
+        return Err(AppError::InvalidInput(
+            "chat members must be existing users in the same workspace".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::CreateUser;
+    use anyhow::Result;
+    use sqlx::postgres::PgListener;
+
+    #[tokio::test]
+    async fn test_create_single_chat() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = CreateChat {
+            name: None,
+            r#type: ChatType::Single,
+            members: vec![1, 2],
+        };
+        let chat = state.create_chat(input, 1).await?;
+        assert_eq!(chat.r#type, ChatType::Single);
+        assert_eq!(chat.members, vec![1, 2]);
+        assert_eq!(chat.owner_id, None);
+
+        state.delete_chat(chat.id, 1).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_chat_with_invalid_members() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+
+        // 单聊必须正好两人
+        let input = CreateChat {
+            name: None,
+            r#type: ChatType::Single,
+            members: vec![1, 2, 3],
+        };
+        let ret = state.create_chat(input, 1).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+
+        // 群聊至少三人
+        let input = CreateChat {
+            name: None,
+            r#type: ChatType::Group,
+            members: vec![1, 2],
+        };
+        let ret = state.create_chat(input, 1).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+
+        // 成员必须是已存在的用户
+        let input = CreateChat {
+            name: None,
+            r#type: ChatType::Single,
+            members: vec![1, 99999],
+        };
+        let ret = state.create_chat(input, 1).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+
+        // 成员必须与创建者在同一工作空间
+        let other = state
+            .create_user(CreateUser {
+                full_name: "other".to_string(),
+                email: "other@example.com".to_string(),
+                workspace: "other".to_string(),
+                password: "123456".to_string(),
+            })
+            .await?;
+        let input = CreateChat {
+            name: None,
+            r#type: ChatType::Single,
+            members: vec![1, other.id],
+        };
+        let ret = state.create_chat(input, 1).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_channel_owner_rules() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = CreateChat {
+            name: Some("test_channel_owner_rules".to_string()),
+            r#type: ChatType::PublicChannel,
+            members: vec![2],
+        };
+        let chat = state.create_chat(input, 1).await?;
+        assert_eq!(chat.owner_id, Some(1));
+        // 成员按用户ID排列
+        assert_eq!(chat.members, vec![1, 2]);
+
+        // 非所有者不能修改频道
+        let update = UpdateChat {
+            name: Some("renamed".to_string()),
+            members: None,
+        };
+        let ret = state.update_chat(chat.id, update, 2).await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+
+        // 所有者可以修改频道
+        let update = UpdateChat {
+            name: None,
+            members: Some(vec![1, 2, 3]),
+        };
+        let chat = state.update_chat(chat.id, update, 1).await?;
+        assert_eq!(chat.members, vec![1, 2, 3]);
+        assert_eq!(chat.name.as_deref(), Some("test_channel_owner_rules"));
+
+        // 成员列表不能漏掉所有者
+        let update = UpdateChat {
+            name: None,
+            members: Some(vec![2, 3]),
+        };
+        let ret = state.update_chat(chat.id, update, 1).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+        let chat = state.get_chat_by_id(chat.id).await?.expect("chat exists");
+        assert_eq!(chat.members, vec![1, 2, 3]);
+
+        // 成员可以在聊天列表中看到该频道
+        let chats = state.fetch_chats(1).await?;
+        assert!(chats.iter().any(|c| c.chat.id == chat.id));
+
+        state.delete_chat(chat.id, 1).await?;
+        assert!(state.get_chat_by_id(chat.id).await?.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn send_typing_should_notify() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = CreateChat {
+            name: None,
+            r#type: ChatType::Single,
+            members: vec![1, 2],
+        };
+        let chat = state.create_chat(input, 1).await?;
+
+        let mut listener = PgListener::connect_with(&state.pool).await?;
+        listener.listen("chat_typing").await?;
+        state.send_typing(chat.id, 1).await?;
+        let notif = listener.recv().await?;
+        let payload: serde_json::Value = serde_json::from_str(notif.payload())?;
+        assert_eq!(payload["chat_id"], chat.id);
+        assert_eq!(payload["members"], serde_json::json!([1, 2]));
+
+        let ret = state.send_typing(chat.id, 3).await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn chat_changes_should_notify() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let mut listener = PgListener::connect_with(&state.pool).await?;
+        listener
+            .listen_all(["chat_updated", "chat_member_updated"])
+            .await?;
+
+        // 新建聊天只发送一条 chat_updated，成员不再单独通知
+        let input = CreateChat {
+            name: None,
+            r#type: ChatType::Group,
+            members: vec![1, 2, 3],
+        };
+        let chat = state.create_chat(input, 1).await?;
+        let notif = listener.recv().await?;
+        assert_eq!(notif.channel(), "chat_updated");
+        let payload: serde_json::Value = serde_json::from_str(notif.payload())?;
+        assert_eq!(payload["op"], "INSERT");
+        assert_eq!(payload["new"]["members"], serde_json::json!([1, 2, 3]));
+
+        let input = UpdateChat {
+            name: Some("team".to_string()),
+            members: None,
+        };
+        state.update_chat(chat.id, input, 1).await?;
+        let notif = listener.recv().await?;
+        assert_eq!(notif.channel(), "chat_updated");
+        let payload: serde_json::Value = serde_json::from_str(notif.payload())?;
+        assert_eq!(payload["op"], "UPDATE");
+        assert_eq!(payload["new"]["name"], "team");
+        assert_eq!(payload["new"]["members"], serde_json::json!([1, 2, 3]));
+
+        state.delete_chat(chat.id, 1).await?;
+        let notif = listener.recv().await?;
+        assert_eq!(notif.channel(), "chat_updated");
+        let payload: serde_json::Value = serde_json::from_str(notif.payload())?;
+        assert_eq!(payload["op"], "DELETE");
+        assert_eq!(payload["old"]["members"], serde_json::json!([1, 2, 3]));
+        Ok(())
+    }
+}
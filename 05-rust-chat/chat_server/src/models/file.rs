@@ -0,0 +1,123 @@
+use crate::error::AppError;
+use crate::models::ChatFile;
+use sha1::{Digest, Sha1};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const FILE_URL_PREFIX: &str = "/api/files/";
+
+impl ChatFile {
+    /// 根据文件内容生成附件信息，扩展名取自原始文件名并转为小写，
+    /// 缺省或含有字母数字以外的字符时为 bin，以保证生成的地址能被重新解析
+    pub fn new(ws_id: i64, filename: &str, data: &[u8]) -> Self {
+        let hash = Sha1::digest(data);
+        let ext = Path::new(filename)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase())
+            .filter(|ext| !ext.is_empty() && ext.chars().all(|c| c.is_ascii_alphanumeric()))
+            .unwrap_or_else(|| "bin".to_string());
+        Self {
+            ws_id,
+            ext,
+            hash: hex::encode(hash),
+        }
+    }
+
+    /// 附件的访问地址
+    pub fn url(&self) -> String {
+        format!("{}{}", FILE_URL_PREFIX, self.hash_to_path())
+    }
+
+    /// 附件在本地的存储路径
+    pub fn path(&self, base_dir: &Path) -> PathBuf {
+        base_dir.join(self.hash_to_path())
+    }
+
+    // 将 hash 拆成 3 段，避免单个目录下文件过多：{ws_id}/abc/def/{rest}.{ext}
+    fn hash_to_path(&self) -> String {
+        let (part1, part2) = self.hash.split_at(3);
+        let (part2, part3) = part2.split_at(3);
+        format!("{}/{}/{}/{}.{}", self.ws_id, part1, part2, part3, self.ext)
+    }
+}
+
+impl FromStr for ChatFile {
+    type Err = AppError;
+
+    /// 解析附件地址，如 `/api/files/1/0a0/a9f/2a6772942557ab5355d76af442f8f65e01.png`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || AppError::InvalidInput(format!("invalid file url: {}", s));
+
+        let path = s.strip_prefix(FILE_URL_PREFIX).ok_or_else(invalid)?;
+        let parts: Vec<&str> = path.split('/').collect();
+        let [ws_id, part1, part2, rest] = parts[..] else {
+            return Err(invalid());
+        };
+        let ws_id = ws_id.parse().map_err(|_| invalid())?;
+        let (part3, ext) = rest.split_once('.').ok_or_else(invalid)?;
+
+        let hash = format!("{}{}{}", part1, part2, part3);
+        let is_valid = part1.len() == 3
+            && part2.len() == 3
+            && hash.len() == 40
+            && hash.chars().all(|c| c.is_ascii_hexdigit())
+            && !ext.is_empty()
+            && ext.chars().all(|c| c.is_ascii_alphanumeric());
+        if !is_valid {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            ws_id,
+            ext: ext.to_string(),
+            hash,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn chat_file_new_should_work() {
+        let file = ChatFile::new(1, "test.PNG", b"hello world");
+        assert_eq!(file.ext, "png");
+        assert_eq!(file.hash, "2aae6c35c94fcfb415dbe95f408b9ce91ee846ed");
+        assert_eq!(
+            file.url(),
+            "/api/files/1/2aa/e6c/35c94fcfb415dbe95f408b9ce91ee846ed.png"
+        );
+        assert_eq!(
+            file.path(Path::new("/tmp/chat")),
+            PathBuf::from("/tmp/chat/1/2aa/e6c/35c94fcfb415dbe95f408b9ce91ee846ed.png")
+        );
+    }
+
+    #[test]
+    fn chat_file_from_str_should_work() -> Result<()> {
+        let file = ChatFile::new(1, "test", b"hello world");
+        assert_eq!(file.ext, "bin");
+        let parsed: ChatFile = file.url().parse()?;
+        assert_eq!(parsed, file);
+
+        // 任意文件名生成的地址都能被重新解析
+        for filename in [
+            "a.tar.gz", "a.", ".env", "a.p-n g", "a.中文", "a.JPEG", "a./x",
+        ] {
+            let file = ChatFile::new(1, filename, b"hello world");
+            let parsed: ChatFile = file.url().parse()?;
+            assert_eq!(parsed, file, "{}", filename);
+        }
+        assert_eq!(ChatFile::new(1, "a.p-n g", b"").ext, "bin");
+        assert_eq!(ChatFile::new(1, "a.tar.GZ", b"").ext, "gz");
+
+        assert!("/api/files/1/../../etc/passwd".parse::<ChatFile>().is_err());
+        assert!("/files/1/2aa/e6c/35c94fcfb415dbe95f408b9ce91ee846ed.png"
+            .parse::<ChatFile>()
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,606 @@
+use super::chat::{lock_chat, lock_chat_for_member, validate_chat};
+use crate::error::AppError;
+use crate::models::{Chat, ChatMember, ChatRole, ChatType, User};
+use crate::AppState;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection};
+
+/// 邀请链接默认有效期（秒）
+const DEFAULT_INVITATION_SECS: i64 = 60 * 60 * 24 * 7;
+/// 邀请链接最长有效期（秒）
+const MAX_INVITATION_SECS: i64 = 60 * 60 * 24 * 30;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChatMembers {
+    pub members: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateMemberRole {
+    pub role: ChatRole,
+}
+
+/// 创建邀请链接参数，`expires_in_secs` 为空时使用默认有效期
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateInvitation {
+    pub expires_in_secs: Option<i64>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct ChatInvitation {
+    pub code: String,
+    pub chat_id: i64,
+    pub created_by: i64,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 用户在聊天中的角色，不是成员时返回 None
+pub fn role_of(chat: &Chat, user_id: i64) -> Option<ChatRole> {
+    if !chat.members.contains(&user_id) {
+        None
+    } else if chat.owner_id == Some(user_id) {
+        Some(ChatRole::Owner)
+    } else if chat.admins.contains(&user_id) {
+        Some(ChatRole::Admin)
+    } else {
+        Some(ChatRole::Member)
+    }
+}
+
+impl AppState {
+    /// 查询聊天成员
+    /// 只有聊天成员可以查看，按加入时间排列
+    ///
+    /// # 参数
+    /// * `id` - 聊天ID
+    /// * `user_id` - 查询者ID
+    ///
+    /// # 返回
+    /// * `Result<Vec<ChatMember>, AppError>` - 成功则返回成员列表，失败则返回错误
+    pub async fn fetch_chat_members(
+        &self,
+        id: i64,
+        user_id: i64,
+    ) -> Result<Vec<ChatMember>, AppError> {
+        self.get_chat_for_member(id, user_id).await?;
+        let members = sqlx::query_as(
+            r#"
+            SELECT u.id AS user_id, u.fullname, u.email, cm.role, cm.joined_at, cm.muted_until
+            FROM chat_members cm
+                     JOIN users u ON u.id = cm.user_id
+            WHERE cm.chat_id = $1
+            ORDER BY cm.joined_at, u.id
+            "#,
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(members)
+    }
+
+    /// 添加聊天成员
+    /// 频道只有所有者和管理员可以添加成员，群聊成员均可添加，单聊不能修改成员
+    ///
+    /// # 参数
+    /// * `id` - 聊天ID
+    /// * `input` - 要添加的成员
+    /// * `user_id` - 操作者ID
+    ///
+    /// # 返回
+    /// * `Result<Chat, AppError>` - 成功则返回修改后的聊天实例，失败则返回错误
+    pub async fn add_chat_members(
+        &self,
+        id: i64,
+        input: ChatMembers,
+        user_id: i64,
+    ) -> Result<Chat, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let chat = lock_chat_for_member(&mut tx, id, user_id).await?;
+        ensure_can_manage_members(&chat, user_id)?;
+        if let Some(member) = input.members.iter().find(|m| chat.members.contains(m)) {
+            return Err(AppError::InvalidInput(format!(
+                "user {} is already a member of chat {}",
+                member, id
+            )));
+        }
+
+        let mut members = chat.members.clone();
+        members.extend(&input.members);
+        validate_chat(
+            chat.r#type,
+            chat.name.as_deref(),
+            &members,
+            user_id,
+            &self.pool,
+        )
+        .await?;
+        insert_members(&mut tx, id, &input.members).await?;
+        tx.commit().await?;
+        self.get_chat_by_id(id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("chat {}", id)))
+    }
+
+    /// 移除聊天成员
+    /// 所有者不能被移除；频道管理员只能移除普通成员，所有者可以移除任何人；
+    /// 群聊移除后不能少于三人
+    ///
+    /// # 参数
+    /// * `id` - 聊天ID
+    /// * `input` - 要移除的成员
+    /// * `user_id` - 操作者ID
+    ///
+    /// # 返回
+    /// * `Result<Chat, AppError>` - 成功则返回修改后的聊天实例，失败则返回错误
+    pub async fn remove_chat_members(
+        &self,
+        id: i64,
+        input: ChatMembers,
+        user_id: i64,
+    ) -> Result<Chat, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let chat = lock_chat_for_member(&mut tx, id, user_id).await?;
+        let role = ensure_can_manage_members(&chat, user_id)?;
+        for member in &input.members {
+            match role_of(&chat, *member) {
+                None => {
+                    return Err(AppError::InvalidInput(format!(
+                        "user {} is not a member of chat {}",
+                        member, id
+                    )))
+                }
+                Some(ChatRole::Owner) => {
+                    return Err(AppError::Forbidden(
+                        "the owner cannot be removed from the chat".to_string(),
+                    ))
+                }
+                Some(ChatRole::Admin) if role != ChatRole::Owner => {
+                    return Err(AppError::Forbidden(
+                        "only the owner can remove an admin".to_string(),
+                    ))
+                }
+                _ => {}
+            }
+        }
+        // 移除后的成员仍需满足聊天类型的要求，如群聊至少三人
+        let remaining: Vec<i64> = chat
+            .members
+            .iter()
+            .copied()
+            .filter(|m| !input.members.contains(m))
+            .collect();
+        validate_chat(
+            chat.r#type,
+            chat.name.as_deref(),
+            &remaining,
+            user_id,
+            &self.pool,
+        )
+        .await?;
+
+        delete_members(&mut tx, id, &input.members).await?;
+        tx.commit().await?;
+        self.get_chat_by_id(id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("chat {}", id)))
+    }
+
+    /// 退出聊天
+    /// 单聊不能退出，群聊只剩三人时不能退出，频道所有者需要先删除频道
+    ///
+    /// # 参数
+    /// * `id` - 聊天ID
+    /// * `user_id` - 退出的用户ID
+    ///
+    /// # 返回
+    /// * `Result<(), AppError>` - 成功则返回空，失败则返回错误
+    pub async fn leave_chat(&self, id: i64, user_id: i64) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        let chat = lock_chat_for_member(&mut tx, id, user_id).await?;
+        if chat.r#type == ChatType::Single {
+            return Err(AppError::InvalidInput(
+                "cannot leave a single chat".to_string(),
+            ));
+        }
+        if chat.owner_id == Some(user_id) {
+            return Err(AppError::Forbidden(
+                "the owner cannot leave the chat".to_string(),
+            ));
+        }
+        if chat.r#type == ChatType::Group && chat.members.len() <= 3 {
+            return Err(AppError::InvalidInput(
+                "group chat must have at least 3 members".to_string(),
+            ));
+        }
+
+        delete_members(&mut tx, id, &[user_id]).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// 修改成员角色
+    /// 只有频道所有者可以任命或撤销管理员
+    ///
+    /// # 参数
+    /// * `id` - 聊天ID
+    /// * `member_id` - 成员ID
+    /// * `input` - 新的角色
+    /// * `user_id` - 操作者ID
+    ///
+    /// # 返回
+    /// * `Result<Chat, AppError>` - 成功则返回修改后的聊天实例，失败则返回错误
+    pub async fn update_member_role(
+        &self,
+        id: i64,
+        member_id: i64,
+        input: UpdateMemberRole,
+        user_id: i64,
+    ) -> Result<Chat, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let chat = lock_chat_for_member(&mut tx, id, user_id).await?;
+        if !is_channel(&chat) {
+            return Err(AppError::InvalidInput(
+                "only channels have member roles".to_string(),
+            ));
+        }
+        if role_of(&chat, user_id) != Some(ChatRole::Owner) {
+            return Err(AppError::Forbidden(
+                "only the owner can change member roles".to_string(),
+            ));
+        }
+        match (role_of(&chat, member_id), input.role) {
+            (None, _) => {
+                return Err(AppError::NotFound(format!(
+                    "member {} of chat {}",
+                    member_id, id
+                )))
+            }
+            (Some(ChatRole::Owner), _) | (_, ChatRole::Owner) => {
+                return Err(AppError::InvalidInput(
+                    "chat ownership cannot be changed".to_string(),
+                ))
+            }
+            _ => {}
+        }
+        sqlx::query("UPDATE chat_members SET role = $3 WHERE chat_id = $1 AND user_id = $2")
+            .bind(id)
+            .bind(member_id)
+            .bind(input.role)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        self.get_chat_by_id(id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("chat {}", id)))
+    }
+
+    /// 创建邀请链接
+    /// 私有频道的所有者和管理员可以创建有时效的邀请链接
+    ///
+    /// # 参数
+    /// * `id` - 聊天ID
+    /// * `input` - 邀请链接参数
+    /// * `user_id` - 操作者ID
+    ///
+    /// # 返回
+    /// * `Result<ChatInvitation, AppError>` - 成功则返回邀请链接，失败则返回错误
+    pub async fn create_invitation(
+        &self,
+        id: i64,
+        input: CreateInvitation,
+        user_id: i64,
+    ) -> Result<ChatInvitation, AppError> {
+        let chat = self.get_chat_for_member(id, user_id).await?;
+        if chat.r#type != ChatType::PrivateChannel {
+            return Err(AppError::InvalidInput(
+                "invitations are only available for private channels".to_string(),
+            ));
+        }
+        ensure_can_manage_members(&chat, user_id)?;
+        let secs = input.expires_in_secs.unwrap_or(DEFAULT_INVITATION_SECS);
+        if !(1..=MAX_INVITATION_SECS).contains(&secs) {
+            return Err(AppError::InvalidInput(format!(
+                "invitation must expire within {} seconds",
+                MAX_INVITATION_SECS
+            )));
+        }
+
+        let mut buf = [0u8; 16];
+        OsRng.fill_bytes(&mut buf);
+        let invitation = sqlx::query_as(
+            r#"
+            INSERT INTO chat_invitations (code, chat_id, created_by, expires_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING code, chat_id, created_by, expires_at, created_at
+            "#,
+        )
+        .bind(hex::encode(buf))
+        .bind(id)
+        .bind(user_id)
+        .bind(Utc::now() + Duration::seconds(secs))
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(invitation)
+    }
+
+    /// 接受邀请
+    /// 通过未过期的邀请链接加入私有频道，只能加入同一工作空间的频道，已是成员时直接返回
+    ///
+    /// # 参数
+    /// * `code` - 邀请码
+    /// * `user` - 接受邀请的用户
+    ///
+    /// # 返回
+    /// * `Result<Chat, AppError>` - 成功则返回加入的聊天实例，失败则返回错误
+    pub async fn accept_invitation(&self, code: &str, user: &User) -> Result<Chat, AppError> {
+        let invitation: Option<(i64,)> = sqlx::query_as(
+            r#"
+            SELECT i.chat_id
+            FROM chat_invitations i
+                     JOIN users u ON u.id = i.created_by
+            WHERE i.code = $1 AND i.expires_at > now() AND u.ws_id = $2
+            "#,
+        )
+        .bind(code)
+        .bind(user.ws_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some((chat_id,)) = invitation else {
+            return Err(AppError::NotFound(format!("invitation {}", code)));
+        };
+
+        let mut tx = self.pool.begin().await?;
+        let Some(chat) = lock_chat(&mut tx, chat_id).await? else {
+            return Err(AppError::NotFound(format!("invitation {}", code)));
+        };
+        if chat.members.contains(&user.id) {
+            return Ok(chat);
+        }
+        insert_members(&mut tx, chat_id, &[user.id]).await?;
+        tx.commit().await?;
+        self.get_chat_by_id(chat_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("chat {}", chat_id)))
+    }
+}
+
+/// 以普通成员身份加入聊天，已是成员的忽略
+async fn insert_members(conn: &mut PgConnection, id: i64, members: &[i64]) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+        INSERT INTO chat_members (chat_id, user_id)
+        SELECT $1, m.user_id
+        FROM unnest($2::bigint[]) AS m(user_id)
+        ON CONFLICT (chat_id, user_id) DO NOTHING
+        "#,
+    )
+    .bind(id)
+    .bind(members)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// 将用户移出聊天
+async fn delete_members(conn: &mut PgConnection, id: i64, members: &[i64]) -> Result<(), AppError> {
+    sqlx::query("DELETE FROM chat_members WHERE chat_id = $1 AND user_id = ANY($2)")
+        .bind(id)
+        .bind(members)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+fn is_channel(chat: &Chat) -> bool {
+    matches!(
+        chat.r#type,
+        ChatType::PrivateChannel | ChatType::PublicChannel
+    )
+}
+
+/// 单聊不能修改成员，频道只有所有者和管理员可以管理成员，群聊成员均可管理
+fn ensure_can_manage_members(chat: &Chat, user_id: i64) -> Result<ChatRole, AppError> {
+    if chat.r#type == ChatType::Single {
+        return Err(AppError::InvalidInput(
+            "members of a single chat cannot be changed".to_string(),
+        ));
+    }
+    let role = role_of(chat, user_id).unwrap_or(ChatRole::Member);
+    if is_channel(chat) && role == ChatRole::Member {
+        return Err(AppError::Forbidden(format!(
+            "only the owner or admins can manage members of chat {}",
+            chat.id
+        )));
+    }
+    Ok(role)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CreateChat, CreateUser};
+    use anyhow::Result;
+    use sqlx::postgres::PgListener;
+
+    async fn create_channel(state: &AppState, name: &str) -> Result<Chat> {
+        let input = CreateChat {
+            name: Some(name.to_string()),
+            r#type: ChatType::PrivateChannel,
+            members: vec![1],
+        };
+        Ok(state.create_chat(input, 1).await?)
+    }
+
+    fn members(ids: &[i64]) -> ChatMembers {
+        ChatMembers {
+            members: ids.to_vec(),
+        }
+    }
+
+    #[tokio::test]
+    async fn channel_member_roles_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let chat = create_channel(&state, "member_roles").await?;
+
+        let mut listener = PgListener::connect_with(&state.pool).await?;
+        listener.listen("chat_member_updated").await?;
+        let chat = state.add_chat_members(chat.id, members(&[2, 3]), 1).await?;
+        assert_eq!(chat.members, vec![1, 2, 3]);
+        // 新成员各收到一条通知
+        for user_id in [2, 3] {
+            let notif = listener.recv().await?;
+            let payload: serde_json::Value = serde_json::from_str(notif.payload())?;
+            assert_eq!(payload["op"], "INSERT");
+            assert_eq!(payload["user_id"], user_id);
+            assert_eq!(payload["chat"]["members"], serde_json::json!([1, 2, 3]));
+        }
+        let ret = state.add_chat_members(chat.id, members(&[2]), 1).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+
+        // 普通成员不能管理成员
+        let ret = state.remove_chat_members(chat.id, members(&[3]), 2).await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+
+        let admin = UpdateMemberRole {
+            role: ChatRole::Admin,
+        };
+        let ret = state.update_member_role(chat.id, 2, admin.clone(), 2).await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+        let chat = state.update_member_role(chat.id, 2, admin, 1).await?;
+        assert_eq!(role_of(&chat, 2), Some(ChatRole::Admin));
+
+        // 管理员可以移除普通成员，但不能移除所有者
+        let ret = state.remove_chat_members(chat.id, members(&[1]), 2).await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+        let chat = state.remove_chat_members(chat.id, members(&[3]), 2).await?;
+        assert_eq!(chat.members, vec![1, 2]);
+
+        // 所有者不能退出，管理员退出后同时失去管理员身份
+        let ret = state.leave_chat(chat.id, 1).await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+        state.leave_chat(chat.id, 2).await?;
+        let chat = state.get_chat_by_id(chat.id).await?.unwrap();
+        assert_eq!(chat.members, vec![1]);
+        assert!(chat.admins.is_empty());
+
+        let members = state.fetch_chat_members(chat.id, 1).await?;
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].role, ChatRole::Owner);
+        let ret = state.fetch_chat_members(chat.id, 2).await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn add_members_should_stay_in_workspace() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let chat = create_channel(&state, "add_members_workspace").await?;
+        let other = state
+            .create_user(CreateUser {
+                full_name: "other".to_string(),
+                email: "other@example.com".to_string(),
+                workspace: "other".to_string(),
+                password: "123456".to_string(),
+            })
+            .await?;
+
+        let ret = state
+            .add_chat_members(chat.id, members(&[2, other.id]), 1)
+            .await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+        let chat = state.get_chat_by_id(chat.id).await?.unwrap();
+        assert_eq!(chat.members, vec![1]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn group_should_keep_at_least_three_members() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let fourth = state
+            .create_user(CreateUser {
+                full_name: "赵六".to_string(),
+                email: "zhaoliu@example.com".to_string(),
+                workspace: "default".to_string(),
+                password: "123456".to_string(),
+            })
+            .await?;
+        let input = CreateChat {
+            name: None,
+            r#type: ChatType::Group,
+            members: vec![1, 2, 3, fourth.id],
+        };
+        let chat = state.create_chat(input, 1).await?;
+
+        let ret = state
+            .remove_chat_members(chat.id, members(&[3, fourth.id]), 1)
+            .await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+        let chat = state
+            .remove_chat_members(chat.id, members(&[fourth.id]), 1)
+            .await?;
+        assert_eq!(chat.members, vec![1, 2, 3]);
+
+        let ret = state.remove_chat_members(chat.id, members(&[3]), 1).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+        let ret = state.leave_chat(chat.id, 2).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+        let chat = state.get_chat_by_id(chat.id).await?.unwrap();
+        assert_eq!(chat.members, vec![1, 2, 3]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invitation_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let chat = create_channel(&state, "invitation").await?;
+
+        let input = CreateInvitation {
+            expires_in_secs: Some(0),
+        };
+        let ret = state.create_invitation(chat.id, input, 1).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+        let invitation = state
+            .create_invitation(chat.id, CreateInvitation::default(), 1)
+            .await?;
+
+        let user = state.find_user_by_id(2).await?.unwrap();
+        let joined = state.accept_invitation(&invitation.code, &user).await?;
+        assert_eq!(joined.members, vec![1, 2]);
+        // 重复接受不会重复加入
+        let joined = state.accept_invitation(&invitation.code, &user).await?;
+        assert_eq!(joined.members, vec![1, 2]);
+
+        sqlx::query("UPDATE chat_invitations SET expires_at = now() WHERE code = $1")
+            .bind(&invitation.code)
+            .execute(&state.pool)
+            .await?;
+        let user = state.find_user_by_id(3).await?.unwrap();
+        let ret = state.accept_invitation(&invitation.code, &user).await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn concurrent_member_changes_should_not_overwrite() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let user = state.find_user_by_id(2).await?.unwrap();
+        for i in 0..5 {
+            let chat = create_channel(&state, &format!("concurrent_{}", i)).await?;
+            let invitation = state
+                .create_invitation(chat.id, CreateInvitation::default(), 1)
+                .await?;
+            let (joined, added) = tokio::join!(
+                state.accept_invitation(&invitation.code, &user),
+                state.add_chat_members(chat.id, members(&[3]), 1),
+            );
+            joined?;
+            added?;
+            let chat = state.get_chat_by_id(chat.id).await?.unwrap();
+            assert_eq!(chat.members, vec![1, 2, 3]);
+        }
+        Ok(())
+    }
+}
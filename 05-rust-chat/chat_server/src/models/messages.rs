@@ -0,0 +1,551 @@
+use crate::error::AppError;
+use crate::models::{ChatFile, ChatRead, Message};
+use crate::AppState;
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_PAGE_SIZE: u64 = 20;
+const MAX_PAGE_SIZE: u64 = 100;
+/// 消息发送后允许编辑的时间（分钟）
+const MESSAGE_EDIT_WINDOW_MINUTES: i64 = 15;
+/// 消息内容的最大长度（JSON 转义后的字节数）
+/// 新消息通过 pg_notify 推送，Postgres 拒绝 8000 字节及以上的负载
+const MAX_CONTENT_LEN: usize = 4000;
+/// 每条消息最多附带的图片数量
+const MAX_IMAGES: usize = 9;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateMessage {
+    pub content: String,
+    #[serde(default)]
+    pub images: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateMessage {
+    pub content: String,
+}
+
+/// 消息分页参数，`last_id` 为上一页最后（最早）一条消息的ID，为空时从最新消息开始
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ListMessages {
+    pub last_id: Option<i64>,
+    pub limit: Option<u64>,
+}
+
+/// 标记已读参数，`message_id` 为空时标记到聊天中最新的一条消息
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MarkRead {
+    pub message_id: Option<i64>,
+}
+
+impl AppState {
+    /// 发送消息
+    /// 发送者必须是聊天成员，消息内容与图片不能同时为空，图片须为上传接口返回的附件地址，
+    /// 内容长度与图片数量有上限
+    ///
+    /// # 参数
+    /// * `input` - 包含消息内容的CreateMessage结构体
+    /// * `chat_id` - 聊天ID
+    /// * `user_id` - 发送者ID
+    ///
+    /// # 返回
+    /// * `Result<Message, AppError>` - 成功则返回新创建的消息实例，失败则返回错误
+    pub async fn create_message(
+        &self,
+        input: CreateMessage,
+        chat_id: i64,
+        user_id: i64,
+    ) -> Result<Message, AppError> {
+        if input.content.trim().is_empty() && input.images.is_empty() {
+            return Err(AppError::InvalidInput(
+                "message content cannot be empty".to_string(),
+            ));
+        }
+        ensure_content_len(&input.content)?;
+        if input.images.len() > MAX_IMAGES {
+            return Err(AppError::InvalidInput(format!(
+                "a message can have at most {} images",
+                MAX_IMAGES
+            )));
+        }
+        for image in &input.images {
+            image.parse::<ChatFile>()?;
+        }
+        self.get_chat_for_member(chat_id, user_id).await?;
+
+        let message = sqlx::query_as(
+            r#"
+            INSERT INTO messages (chat_id, sender_id, content, images)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, chat_id, sender_id, content, images, created_at, edited_at, deleted_at
+            "#,
+        )
+        .bind(chat_id)
+        .bind(user_id)
+        .bind(&input.content)
+        .bind(&input.images)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(message)
+    }
+
+    /// 查询消息列表
+    /// 按消息ID倒序做 keyset 分页，只有聊天成员可以查看
+    ///
+    /// # 参数
+    /// * `input` - 分页参数
+    /// * `chat_id` - 聊天ID
+    /// * `user_id` - 查询者ID
+    ///
+    /// # 返回
+    /// * `Result<Vec<Message>, AppError>` - 成功则返回由新到旧排列的消息列表，失败则返回错误
+    pub async fn list_messages(
+        &self,
+        input: ListMessages,
+        chat_id: i64,
+        user_id: i64,
+    ) -> Result<Vec<Message>, AppError> {
+        self.get_chat_for_member(chat_id, user_id).await?;
+
+        let last_id = input.last_id.unwrap_or(i64::MAX);
+        let limit = input
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+        let messages = sqlx::query_as(
+            r#"
+            SELECT id, chat_id, sender_id, content, images, created_at, edited_at, deleted_at
+            FROM messages
+            WHERE chat_id = $1 AND id < $2
+            ORDER BY id DESC
+            LIMIT $3
+            "#,
+        )
+        .bind(chat_id)
+        .bind(last_id)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(messages)
+    }
+
+    /// 编辑消息
+    /// 只有发送者可以在发送后的编辑时限内修改消息内容，已删除的消息不能编辑，
+    /// 新内容的长度上限与发送消息相同
+    ///
+    /// # 参数
+    /// * `input` - 包含新内容的UpdateMessage结构体
+    /// * `chat_id` - 聊天ID
+    /// * `id` - 消息ID
+    /// * `user_id` - 操作者ID
+    ///
+    /// # 返回
+    /// * `Result<Message, AppError>` - 成功则返回修改后的消息实例，失败则返回错误
+    pub async fn update_message(
+        &self,
+        input: UpdateMessage,
+        chat_id: i64,
+        id: i64,
+        user_id: i64,
+    ) -> Result<Message, AppError> {
+        let message = self.get_message_for_member(chat_id, id, user_id).await?;
+        ensure_sender(&message, user_id)?;
+        if Utc::now() - message.created_at > Duration::minutes(MESSAGE_EDIT_WINDOW_MINUTES) {
+            return Err(AppError::Forbidden(format!(
+                "message {} can only be edited within {} minutes",
+                id, MESSAGE_EDIT_WINDOW_MINUTES
+            )));
+        }
+        if input.content.trim().is_empty() && message.images.is_empty() {
+            return Err(AppError::InvalidInput(
+                "message content cannot be empty".to_string(),
+            ));
+        }
+        ensure_content_len(&input.content)?;
+
+        let message = sqlx::query_as(
+            r#"
+            UPDATE messages SET content = $1, edited_at = now()
+            WHERE id = $2
+            RETURNING id, chat_id, sender_id, content, images, created_at, edited_at, deleted_at
+            "#,
+        )
+        .bind(&input.content)
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(message)
+    }
+
+    /// 删除消息
+    /// 只有发送者可以删除消息，删除后保留消息记录作为墓碑，清空内容、图片和表情回应
+    ///
+    /// # 参数
+    /// * `chat_id` - 聊天ID
+    /// * `id` - 消息ID
+    /// * `user_id` - 操作者ID
+    ///
+    /// # 返回
+    /// * `Result<Message, AppError>` - 成功则返回删除后的消息墓碑，失败则返回错误
+    pub async fn delete_message(
+        &self,
+        chat_id: i64,
+        id: i64,
+        user_id: i64,
+    ) -> Result<Message, AppError> {
+        let message = self.get_message_for_member(chat_id, id, user_id).await?;
+        ensure_sender(&message, user_id)?;
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM message_reactions WHERE message_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        let message = sqlx::query_as(
+            r#"
+            UPDATE messages SET content = '', images = '{}', deleted_at = now()
+            WHERE id = $1
+            RETURNING id, chat_id, sender_id, content, images, created_at, edited_at, deleted_at
+            "#,
+        )
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(message)
+    }
+
+    /// 查找聊天中未删除的消息并确认用户是该聊天成员
+    pub(crate) async fn get_message_for_member(
+        &self,
+        chat_id: i64,
+        id: i64,
+        user_id: i64,
+    ) -> Result<Message, AppError> {
+        self.get_chat_for_member(chat_id, user_id).await?;
+        let message = sqlx::query_as(
+            r#"
+            SELECT id, chat_id, sender_id, content, images, created_at, edited_at, deleted_at
+            FROM messages
+            WHERE id = $1 AND chat_id = $2 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(id)
+        .bind(chat_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        message.ok_or_else(|| AppError::NotFound(format!("message {}", id)))
+    }
+
+    /// 标记已读
+    /// 记录用户在聊天中读到的最后一条消息，已读位置只会前进不会后退
+    ///
+    /// # 参数
+    /// * `input` - 标记已读参数
+    /// * `chat_id` - 聊天ID
+    /// * `user_id` - 用户ID
+    ///
+    /// # 返回
+    /// * `Result<ChatRead, AppError>` - 成功则返回用户在该聊天中的已读位置，失败则返回错误
+    pub async fn mark_read(
+        &self,
+        input: MarkRead,
+        chat_id: i64,
+        user_id: i64,
+    ) -> Result<ChatRead, AppError> {
+        self.get_chat_for_member(chat_id, user_id).await?;
+
+        let (message_id,): (Option<i64>,) = match input.message_id {
+            Some(id) => sqlx::query_as("SELECT id FROM messages WHERE id = $1 AND chat_id = $2")
+                .bind(id)
+                .bind(chat_id)
+                .fetch_optional(&self.pool)
+                .await?
+                .ok_or_else(|| AppError::NotFound(format!("message {}", id)))?,
+            None => {
+                sqlx::query_as("SELECT max(id) FROM messages WHERE chat_id = $1")
+                    .bind(chat_id)
+                    .fetch_one(&self.pool)
+                    .await?
+            }
+        };
+        let Some(message_id) = message_id else {
+            return Err(AppError::InvalidInput(format!(
+                "chat {} has no messages",
+                chat_id
+            )));
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO chat_reads (chat_id, user_id, last_read_message_id)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (chat_id, user_id) DO UPDATE
+                SET last_read_message_id = EXCLUDED.last_read_message_id, updated_at = now()
+                WHERE chat_reads.last_read_message_id < EXCLUDED.last_read_message_id
+            "#,
+        )
+        .bind(chat_id)
+        .bind(user_id)
+        .bind(message_id)
+        .execute(&self.pool)
+        .await?;
+
+        let read = sqlx::query_as(
+            r#"
+            SELECT chat_id, user_id, last_read_message_id, updated_at
+            FROM chat_reads
+            WHERE chat_id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(chat_id)
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(read)
+    }
+}
+
+/// 只有发送者可以修改或删除消息
+fn ensure_sender(message: &Message, user_id: i64) -> Result<(), AppError> {
+    if message.sender_id != user_id {
+        return Err(AppError::Forbidden(format!(
+            "only the sender can modify message {}",
+            message.id
+        )));
+    }
+    Ok(())
+}
+
+/// 消息内容按 JSON 转义后的字节数计算长度，与通知负载中的大小一致
+fn ensure_content_len(content: &str) -> Result<(), AppError> {
+    let len = serde_json::to_string(content)
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .len();
+    if len > MAX_CONTENT_LEN {
+        return Err(AppError::InvalidInput(format!(
+            "message content is too long, at most {} bytes",
+            MAX_CONTENT_LEN
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Chat, ChatType, CreateChat};
+    use anyhow::Result;
+
+    async fn create_test_chat(state: &AppState) -> Result<Chat> {
+        let input = CreateChat {
+            name: None,
+            r#type: ChatType::Single,
+            members: vec![1, 2],
+        };
+        Ok(state.create_chat(input, 1).await?)
+    }
+
+    #[tokio::test]
+    async fn test_create_message_validation() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let chat = create_test_chat(&state).await?;
+
+        let input = CreateMessage {
+            content: "hello".to_string(),
+            images: vec![],
+        };
+        let ret = state.create_message(input, chat.id, 3).await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+
+        let ret = state
+            .create_message(CreateMessage::default(), chat.id, 1)
+            .await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+
+        let input = CreateMessage {
+            content: "hello".to_string(),
+            images: vec!["https://example.com/a.png".to_string()],
+        };
+        let ret = state.create_message(input, chat.id, 1).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+
+        let input = CreateMessage {
+            content: "\"".repeat(MAX_CONTENT_LEN / 2),
+            images: vec![],
+        };
+        let ret = state.create_message(input, chat.id, 1).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+
+        let input = CreateMessage {
+            content: "长".repeat((MAX_CONTENT_LEN - 2) / 3),
+            images: vec![],
+        };
+        let message = state.create_message(input, chat.id, 1).await?;
+        assert_eq!(message.content.chars().count(), (MAX_CONTENT_LEN - 2) / 3);
+
+        let input = CreateMessage {
+            content: "hello".to_string(),
+            images: vec!["/api/files/1/abc/def/0123.png".to_string(); MAX_IMAGES + 1],
+        };
+        let ret = state.create_message(input, chat.id, 1).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+
+        state.delete_chat(chat.id, 1).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_messages_with_keyset_pagination() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let chat = create_test_chat(&state).await?;
+
+        for i in 0..5 {
+            let input = CreateMessage {
+                content: format!("message {}", i),
+                images: vec![],
+            };
+            state.create_message(input, chat.id, 1 + i % 2).await?;
+        }
+
+        let input = ListMessages {
+            last_id: None,
+            limit: Some(3),
+        };
+        let page1 = state.list_messages(input, chat.id, 2).await?;
+        assert_eq!(page1.len(), 3);
+        assert_eq!(page1[0].content, "message 4");
+
+        let input = ListMessages {
+            last_id: page1.last().map(|m| m.id),
+            limit: Some(3),
+        };
+        let page2 = state.list_messages(input, chat.id, 2).await?;
+        assert_eq!(page2.len(), 2);
+        assert_eq!(page2[1].content, "message 0");
+
+        let ret = state
+            .list_messages(ListMessages::default(), chat.id, 3)
+            .await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+
+        state.delete_chat(chat.id, 1).await?;
+        Ok(())
+    }
+
+    async fn unread_count(state: &AppState, chat_id: i64, user_id: i64) -> Result<Option<i64>> {
+        let chats = state.fetch_chats(user_id).await?;
+        Ok(chats
+            .into_iter()
+            .find(|c| c.chat.id == chat_id)
+            .map(|c| c.unread_count))
+    }
+
+    #[tokio::test]
+    async fn test_mark_read_and_unread_count() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let chat = create_test_chat(&state).await?;
+
+        let ret = state.mark_read(MarkRead::default(), chat.id, 2).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+
+        let mut ids = vec![];
+        for i in 0..3 {
+            let input = CreateMessage {
+                content: format!("message {}", i),
+                images: vec![],
+            };
+            ids.push(state.create_message(input, chat.id, 1).await?.id);
+        }
+        // 自己发送的消息不计入未读
+        assert_eq!(unread_count(&state, chat.id, 1).await?, Some(0));
+        assert_eq!(unread_count(&state, chat.id, 2).await?, Some(3));
+
+        let input = MarkRead {
+            message_id: Some(ids[0]),
+        };
+        let read = state.mark_read(input, chat.id, 2).await?;
+        assert_eq!(read.last_read_message_id, ids[0]);
+        assert_eq!(unread_count(&state, chat.id, 2).await?, Some(2));
+
+        // 已删除的消息不计入未读
+        state.delete_message(chat.id, ids[1], 1).await?;
+        assert_eq!(unread_count(&state, chat.id, 2).await?, Some(1));
+
+        let read = state.mark_read(MarkRead::default(), chat.id, 2).await?;
+        assert_eq!(read.last_read_message_id, ids[2]);
+        assert_eq!(unread_count(&state, chat.id, 2).await?, Some(0));
+
+        // 已读位置不会后退
+        let input = MarkRead {
+            message_id: Some(ids[1]),
+        };
+        let read = state.mark_read(input, chat.id, 2).await?;
+        assert_eq!(read.last_read_message_id, ids[2]);
+
+        let input = MarkRead {
+            message_id: Some(i64::MAX),
+        };
+        let ret = state.mark_read(input, chat.id, 2).await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+        let ret = state.mark_read(MarkRead::default(), chat.id, 3).await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_update_and_delete_message() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let chat = create_test_chat(&state).await?;
+        let input = CreateMessage {
+            content: "hello".to_string(),
+            images: vec![],
+        };
+        let message = state.create_message(input, chat.id, 1).await?;
+        assert!(message.edited_at.is_none());
+
+        let input = UpdateMessage {
+            content: "hello world".to_string(),
+        };
+        let ret = state
+            .update_message(input.clone(), chat.id, message.id, 2)
+            .await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+        let updated = state
+            .update_message(input.clone(), chat.id, message.id, 1)
+            .await?;
+        assert_eq!(updated.content, "hello world");
+        assert!(updated.edited_at.is_some());
+
+        let long = UpdateMessage {
+            content: "a\n".repeat(MAX_CONTENT_LEN / 2),
+        };
+        let ret = state.update_message(long, chat.id, message.id, 1).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+
+        // 超过编辑时限不能再编辑
+        sqlx::query("UPDATE messages SET created_at = now() - interval '1 hour' WHERE id = $1")
+            .bind(message.id)
+            .execute(&state.pool)
+            .await?;
+        let ret = state.update_message(input, chat.id, message.id, 1).await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+
+        let ret = state.delete_message(chat.id, message.id, 2).await;
+        assert!(matches!(ret, Err(AppError::Forbidden(_))));
+        let deleted = state.delete_message(chat.id, message.id, 1).await?;
+        assert!(deleted.deleted_at.is_some());
+        assert!(deleted.content.is_empty());
+
+        // 删除后保留墓碑，不能再次编辑或删除
+        let messages = state
+            .list_messages(ListMessages::default(), chat.id, 2)
+            .await?;
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].deleted_at.is_some());
+        let ret = state.delete_message(chat.id, message.id, 1).await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+        Ok(())
+    }
+}
@@ -1,33 +1,30 @@
+mod chat;
+mod file;
+mod member;
+mod messages;
+mod reaction;
+mod search;
+mod token;
 mod user;
+mod workspace;
 
-use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+pub use chat::{CreateChat, UpdateChat};
+pub use member::{ChatMembers, CreateInvitation, UpdateMemberRole};
+pub use messages::{CreateMessage, ListMessages, MarkRead, UpdateMessage};
+pub use reaction::CreateReaction;
+pub use search::SearchMessages;
+pub use token::RefreshToken;
+pub use user::{CreateUser, SignInUser};
 
+pub use chat_core::{
+    Chat, ChatMember, ChatRead, ChatRole, ChatType, ChatUser, Message, MessageReaction, User,
+    Workspace,
+};
 
-#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
-pub struct User {
-    pub id: i64,
+/// 聊天附件，按内容 sha1 寻址存储在 `{base_dir}/{ws_id}/` 下
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatFile {
     pub ws_id: i64,
-    pub fullname: String,
-    pub email: String,
-    #[sqlx(default)]
-    #[serde(skip)]
-    pub password_hash: Option<String>,
-    pub created_at: DateTime<Utc>,
+    pub ext: String,
+    pub hash: String,
 }
-
-#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
-pub struct Workspace {
-    pub id: i64,
-    pub name: String,
-    pub owner_id: i64,
-    pub created_at: DateTime<Utc>,
-}
-
-#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
-pub struct ChatUser {
-    pub id: i64,
-    pub fullname: String,
-    pub email: String,
-}
\ No newline at end of file
@@ -0,0 +1,196 @@
+use crate::error::AppError;
+use crate::models::MessageReaction;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+
+/// 表情的最大长度（字节），与 message_reactions.emoji 的列宽一致
+const MAX_EMOJI_LEN: usize = 32;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateReaction {
+    pub emoji: String,
+}
+
+impl AppState {
+    /// 添加表情回应
+    /// 聊天成员可以对未删除的消息添加表情回应，重复添加同一表情不会报错
+    ///
+    /// # 参数
+    /// * `input` - 包含表情的CreateReaction结构体
+    /// * `chat_id` - 聊天ID
+    /// * `message_id` - 消息ID
+    /// * `user_id` - 操作者ID
+    ///
+    /// # 返回
+    /// * `Result<MessageReaction, AppError>` - 成功则返回表情回应，失败则返回错误
+    pub async fn add_reaction(
+        &self,
+        input: CreateReaction,
+        chat_id: i64,
+        message_id: i64,
+        user_id: i64,
+    ) -> Result<MessageReaction, AppError> {
+        validate_emoji(&input.emoji)?;
+        self.get_message_for_member(chat_id, message_id, user_id)
+            .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO message_reactions (message_id, user_id, emoji)
+            VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(message_id)
+        .bind(user_id)
+        .bind(&input.emoji)
+        .execute(&self.pool)
+        .await?;
+
+        let reaction = sqlx::query_as(
+            r#"
+            SELECT message_id, user_id, emoji, created_at
+            FROM message_reactions
+            WHERE message_id = $1 AND user_id = $2 AND emoji = $3
+            "#,
+        )
+        .bind(message_id)
+        .bind(user_id)
+        .bind(&input.emoji)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(reaction)
+    }
+
+    /// 移除表情回应
+    /// 只能移除自己添加的表情回应
+    ///
+    /// # 参数
+    /// * `chat_id` - 聊天ID
+    /// * `message_id` - 消息ID
+    /// * `emoji` - 表情
+    /// * `user_id` - 操作者ID
+    ///
+    /// # 返回
+    /// * `Result<(), AppError>` - 成功则返回空，表情回应不存在时返回 NotFound
+    pub async fn remove_reaction(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+        emoji: &str,
+        user_id: i64,
+    ) -> Result<(), AppError> {
+        self.get_message_for_member(chat_id, message_id, user_id)
+            .await?;
+        let result = sqlx::query(
+            "DELETE FROM message_reactions WHERE message_id = $1 AND user_id = $2 AND emoji = $3",
+        )
+        .bind(message_id)
+        .bind(user_id)
+        .bind(emoji)
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!(
+                "reaction {} on message {}",
+                emoji, message_id
+            )));
+        }
+        Ok(())
+    }
+
+    /// 查询消息的表情回应
+    ///
+    /// # 参数
+    /// * `chat_id` - 聊天ID
+    /// * `message_id` - 消息ID
+    /// * `user_id` - 查询者ID
+    ///
+    /// # 返回
+    /// * `Result<Vec<MessageReaction>, AppError>` - 成功则返回按添加时间排列的表情回应，失败则返回错误
+    pub async fn list_reactions(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+        user_id: i64,
+    ) -> Result<Vec<MessageReaction>, AppError> {
+        self.get_message_for_member(chat_id, message_id, user_id)
+            .await?;
+        let reactions = sqlx::query_as(
+            r#"
+            SELECT message_id, user_id, emoji, created_at
+            FROM message_reactions
+            WHERE message_id = $1
+            ORDER BY created_at, user_id
+            "#,
+        )
+        .bind(message_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(reactions)
+    }
+}
+
+fn validate_emoji(emoji: &str) -> Result<(), AppError> {
+    if emoji.is_empty() || emoji.len() > MAX_EMOJI_LEN || emoji.chars().any(char::is_whitespace) {
+        return Err(AppError::InvalidInput(format!(
+            "invalid emoji: {:?}",
+            emoji
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ChatType, CreateChat, CreateMessage};
+    use anyhow::Result;
+
+    #[tokio::test]
+    async fn reactions_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = CreateChat {
+            name: None,
+            r#type: ChatType::Group,
+            members: vec![1, 2, 3],
+        };
+        let chat = state.create_chat(input, 1).await?;
+        let input = CreateMessage {
+            content: "hello".to_string(),
+            images: vec![],
+        };
+        let message = state.create_message(input, chat.id, 1).await?;
+
+        let thumbs_up = CreateReaction {
+            emoji: "👍".to_string(),
+        };
+        let reaction = state
+            .add_reaction(thumbs_up.clone(), chat.id, message.id, 2)
+            .await?;
+        assert_eq!(reaction.emoji, "👍");
+        // 重复添加同一表情不报错
+        state
+            .add_reaction(thumbs_up.clone(), chat.id, message.id, 2)
+            .await?;
+        state
+            .add_reaction(thumbs_up, chat.id, message.id, 3)
+            .await?;
+        assert_eq!(state.list_reactions(chat.id, message.id, 1).await?.len(), 2);
+
+        let ret = state
+            .add_reaction(CreateReaction::default(), chat.id, message.id, 2)
+            .await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+
+        state.remove_reaction(chat.id, message.id, "👍", 2).await?;
+        let ret = state.remove_reaction(chat.id, message.id, "👍", 2).await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+
+        // 删除消息时一并清除表情回应
+        state.delete_message(chat.id, message.id, 1).await?;
+        let ret = state.list_reactions(chat.id, message.id, 1).await;
+        assert!(matches!(ret, Err(AppError::NotFound(_))));
+        Ok(())
+    }
+}
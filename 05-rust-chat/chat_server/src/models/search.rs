@@ -0,0 +1,178 @@
+use crate::error::AppError;
+use crate::models::Message;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+const DEFAULT_PAGE_SIZE: u64 = 20;
+const MAX_PAGE_SIZE: u64 = 100;
+
+/// 消息搜索参数，`before` 为上一页最后一条结果的消息ID，为空时从最新消息开始
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchMessages {
+    pub q: String,
+    pub chat_id: Option<i64>,
+    pub sender_id: Option<i64>,
+    pub before: Option<i64>,
+    pub limit: Option<u64>,
+}
+
+/// 搜索结果，`snippet` 为用 `<mark>` 标出命中词的内容片段
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, PartialEq)]
+pub struct SearchHit {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub message: Message,
+    pub snippet: String,
+}
+
+impl AppState {
+    /// 搜索消息
+    /// 在当前用户所在的聊天中全文搜索未删除的消息，按消息ID倒序做 keyset 分页
+    ///
+    /// # 参数
+    /// * `input` - 搜索参数
+    /// * `user_id` - 查询者ID
+    ///
+    /// # 返回
+    /// * `Result<Vec<SearchHit>, AppError>` - 成功则返回由新到旧排列的搜索结果，失败则返回错误
+    pub async fn search_messages(
+        &self,
+        input: SearchMessages,
+        user_id: i64,
+    ) -> Result<Vec<SearchHit>, AppError> {
+        if input.q.trim().is_empty() {
+            return Err(AppError::InvalidInput(
+                "search query cannot be empty".to_string(),
+            ));
+        }
+        let before = input.before.unwrap_or(i64::MAX);
+        let limit = input
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+        let hits = sqlx::query_as(
+            r#"
+            SELECT m.id, m.chat_id, m.sender_id, m.content, m.images, m.created_at,
+                   m.edited_at, m.deleted_at,
+                   ts_headline('simple', m.content, q.query,
+                               'StartSel=<mark>, StopSel=</mark>, MaxFragments=2') AS snippet
+            FROM messages m
+                     JOIN chat_members cm ON cm.chat_id = m.chat_id AND cm.user_id = $2,
+                 websearch_to_tsquery('simple', $1) AS q(query)
+            WHERE m.content_tsv @@ q.query
+              AND m.deleted_at IS NULL
+              AND ($3::bigint IS NULL OR m.chat_id = $3)
+              AND ($4::bigint IS NULL OR m.sender_id = $4)
+              AND m.id < $5
+            ORDER BY m.id DESC
+            LIMIT $6
+            "#,
+        )
+        .bind(&input.q)
+        .bind(user_id)
+        .bind(input.chat_id)
+        .bind(input.sender_id)
+        .bind(before)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ChatType, CreateChat, CreateMessage};
+    use anyhow::Result;
+    use sqlx::postgres::PgListener;
+
+    #[tokio::test]
+    async fn search_messages_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = CreateChat {
+            name: None,
+            r#type: ChatType::Single,
+            members: vec![1, 2],
+        };
+        let chat = state.create_chat(input, 1).await?;
+        for (i, content) in ["hello rust", "rust is fast", "hello world", "rust again"]
+            .into_iter()
+            .enumerate()
+        {
+            let input = CreateMessage {
+                content: content.to_string(),
+                images: vec![],
+            };
+            state
+                .create_message(input, chat.id, 1 + i as i64 % 2)
+                .await?;
+        }
+
+        let input = SearchMessages {
+            q: "rust".to_string(),
+            limit: Some(2),
+            ..Default::default()
+        };
+        let page1 = state.search_messages(input, 1).await?;
+        assert_eq!(page1.len(), 2);
+        assert_eq!(page1[0].message.content, "rust again");
+        assert_eq!(page1[0].snippet, "<mark>rust</mark> again");
+
+        let input = SearchMessages {
+            q: "rust".to_string(),
+            before: page1.last().map(|hit| hit.message.id),
+            ..Default::default()
+        };
+        let page2 = state.search_messages(input, 1).await?;
+        assert_eq!(page2.len(), 1);
+        assert_eq!(page2[0].message.content, "hello rust");
+
+        let input = SearchMessages {
+            q: "rust".to_string(),
+            sender_id: Some(2),
+            chat_id: Some(chat.id),
+            ..Default::default()
+        };
+        let hits = state.search_messages(input, 1).await?;
+        assert_eq!(hits.len(), 2);
+        assert!(hits.iter().all(|hit| hit.message.sender_id == 2));
+
+        // 非聊天成员搜索不到
+        let input = SearchMessages {
+            q: "rust".to_string(),
+            ..Default::default()
+        };
+        assert!(state.search_messages(input, 3).await?.is_empty());
+
+        let ret = state.search_messages(SearchMessages::default(), 1).await;
+        assert!(matches!(ret, Err(AppError::InvalidInput(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn message_notify_payload_should_not_contain_search_vector() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = CreateChat {
+            name: None,
+            r#type: ChatType::Single,
+            members: vec![1, 2],
+        };
+        let chat = state.create_chat(input, 1).await?;
+
+        let mut listener = PgListener::connect_with(&state.pool).await?;
+        listener.listen("chat_message_created").await?;
+        let input = CreateMessage {
+            content: "hello rust".to_string(),
+            images: vec![],
+        };
+        let message = state.create_message(input, chat.id, 1).await?;
+        let notif = listener.recv().await?;
+        let payload: serde_json::Value = serde_json::from_str(notif.payload())?;
+        assert_eq!(payload["message"]["id"], message.id);
+        assert_eq!(payload["message"]["content"], "hello rust");
+        assert!(payload["message"].get("content_tsv").is_none());
+        Ok(())
+    }
+}
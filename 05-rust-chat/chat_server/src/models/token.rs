@@ -0,0 +1,198 @@
+use crate::error::AppError;
+use crate::models::User;
+use crate::AppState;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chat_core::utils::TokenClaims;
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgConnection;
+
+/// refresh token 有效期（天）
+const REFRESH_TOKEN_DURATION_DAYS: i64 = 30;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefreshToken {
+    pub refresh_token: String,
+}
+
+impl AppState {
+    /// 签发 refresh token
+    /// 数据库中只保存 token 的 sha256 摘要
+    ///
+    /// # 参数
+    /// * `user_id` - 用户ID
+    ///
+    /// # 返回
+    /// * `Result<String, AppError>` - 成功则返回 refresh token 明文，失败则返回错误
+    pub async fn create_refresh_token(&self, user_id: i64) -> Result<String, AppError> {
+        let mut conn = self.pool.acquire().await?;
+        insert_refresh_token(&mut conn, user_id).await
+    }
+
+    /// 轮换 refresh token
+    /// 旧 token 使用后立即失效并换发新的 token，过期或已使用过的 token 返回 Unauthorized
+    ///
+    /// # 参数
+    /// * `token` - 客户端持有的 refresh token
+    ///
+    /// # 返回
+    /// * `Result<(User, String), AppError>` - 成功则返回 token 所属用户与新的 refresh token，失败则返回错误
+    pub async fn rotate_refresh_token(&self, token: &str) -> Result<(User, String), AppError> {
+        let mut tx = self.pool.begin().await?;
+        let row: Option<(i64,)> = sqlx::query_as(
+            r#"
+            DELETE FROM refresh_tokens
+            WHERE token_hash = $1 AND expires_at > now()
+            RETURNING user_id
+            "#,
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some((user_id,)) = row else {
+            return Err(AppError::Unauthorized(
+                "invalid or expired refresh token".to_string(),
+            ));
+        };
+        let user = self
+            .find_user_by_id(user_id)
+            .await?
+            .ok_or_else(|| AppError::Unauthorized(format!("user {} not found", user_id)))?;
+        let new_token = insert_refresh_token(&mut tx, user_id).await?;
+        tx.commit().await?;
+        Ok((user, new_token))
+    }
+
+    /// 吊销 refresh token
+    /// 只能吊销属于自己的 token
+    ///
+    /// # 参数
+    /// * `user_id` - 用户ID
+    /// * `token` - 要吊销的 refresh token
+    ///
+    /// # 返回
+    /// * `Result<bool, AppError>` - 成功则返回是否吊销了 token，失败则返回错误
+    pub async fn revoke_refresh_token(&self, user_id: i64, token: &str) -> Result<bool, AppError> {
+        let result =
+            sqlx::query("DELETE FROM refresh_tokens WHERE token_hash = $1 AND user_id = $2")
+                .bind(hash_token(token))
+                .bind(user_id)
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// 吊销 access token
+    /// 将 jti 加入黑名单直到 token 过期，并顺带清理已过期的黑名单记录
+    ///
+    /// # 参数
+    /// * `claims` - 要吊销的 access token
+    ///
+    /// # 返回
+    /// * `Result<(), AppError>` - 成功则返回空，失败则返回错误
+    pub async fn revoke_access_token(&self, claims: &TokenClaims) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO revoked_tokens (jti, expires_at)
+            VALUES ($1, $2)
+            ON CONFLICT (jti) DO NOTHING
+            "#,
+        )
+        .bind(&claims.jti)
+        .bind(claims.expires_at)
+        .execute(&self.pool)
+        .await?;
+        sqlx::query("DELETE FROM revoked_tokens WHERE expires_at < now()")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// 检查 access token 是否已被吊销
+    ///
+    /// # 参数
+    /// * `jti` - access token 的 jti
+    ///
+    /// # 返回
+    /// * `Result<bool, AppError>` - 成功则返回是否已吊销，失败则返回错误
+    pub async fn is_token_revoked(&self, jti: &str) -> Result<bool, AppError> {
+        let (revoked,): (bool,) =
+            sqlx::query_as("SELECT EXISTS(SELECT 1 FROM revoked_tokens WHERE jti = $1)")
+                .bind(jti)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(revoked)
+    }
+}
+
+async fn insert_refresh_token(conn: &mut PgConnection, user_id: i64) -> Result<String, AppError> {
+    let mut buf = [0u8; 32];
+    OsRng.fill_bytes(&mut buf);
+    let token = hex::encode(buf);
+    let expires_at = Utc::now() + Duration::days(REFRESH_TOKEN_DURATION_DAYS);
+    sqlx::query("INSERT INTO refresh_tokens (user_id, token_hash, expires_at) VALUES ($1, $2, $3)")
+        .bind(user_id)
+        .bind(hash_token(&token))
+        .bind(expires_at)
+        .execute(conn)
+        .await?;
+    Ok(token)
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::CreateUser;
+    use anyhow::Result;
+
+    async fn create_test_user(state: &AppState, email: &str) -> Result<User> {
+        let input = CreateUser {
+            full_name: "Token Test".to_string(),
+            email: email.to_string(),
+            workspace: "test_workspace".to_string(),
+            password: "password123".to_string(),
+        };
+        Ok(state.create_user(input).await?)
+    }
+
+    #[tokio::test]
+    async fn refresh_token_should_rotate() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let user = create_test_user(&state, "refresh_rotate@example.com").await?;
+
+        let token = state.create_refresh_token(user.id).await?;
+        let (owner, new_token) = state.rotate_refresh_token(&token).await?;
+        assert_eq!(owner.id, user.id);
+        assert_ne!(token, new_token);
+
+        // 已使用过的 token 不能再次使用
+        let ret = state.rotate_refresh_token(&token).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+
+        // 只能吊销自己的 token
+        assert!(!state.revoke_refresh_token(user.id + 1, &new_token).await?);
+        assert!(state.revoke_refresh_token(user.id, &new_token).await?);
+        let ret = state.rotate_refresh_token(&new_token).await;
+        assert!(matches!(ret, Err(AppError::Unauthorized(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn access_token_should_be_revoked() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let user = create_test_user(&state, "revoke_access@example.com").await?;
+        let claims = state.dk.decode(&state.ek.sign(user.clone())?)?;
+
+        assert!(!state.is_token_revoked(&claims.jti).await?);
+        state.revoke_access_token(&claims).await?;
+        assert!(state.is_token_revoked(&claims.jti).await?);
+
+        Ok(())
+    }
+}
@@ -1,14 +1,20 @@
+use super::workspace::{set_workspace_owner, upsert_workspace};
 use crate::error::AppError;
 use crate::models::User;
+use crate::AppState;
 use argon2::password_hash::rand_core::OsRng;
 use argon2::password_hash::SaltString;
 use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
 use std::mem;
+
+/// Postgres 唯一约束冲突的错误码
+const UNIQUE_VIOLATION: &str = "23505";
+/// users.email 上的唯一索引
+const EMAIL_INDEX: &str = "email_index";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateUser {
-    pub ws_id: i32,
     pub full_name: String,
     pub email: String,
     pub workspace: String,
@@ -17,59 +23,93 @@ pub struct CreateUser {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SignInUser {
-    pub ws_id: i32,
     pub email: String,
     pub password: String,
 }
 
-impl User {
+impl AppState {
     /// 查找用户
     /// 根据邮箱查找用户
     ///
     /// # 参数
     /// * `email` - 用户邮箱地址
-    /// * `pool` - 数据库连接池
     ///
     /// # 返回
     /// * `Result<Option<Self>, AppError>` - 成功则返回可能存在的用户实例，失败则返回错误
-    pub async fn find_by_email(email: &str, pool: &PgPool) -> Result<Option<Self>, AppError> {
+    #[allow(dead_code)]
+    pub async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, AppError> {
         let user = sqlx::query_as(
             "SELECT id,ws_id, fullname, email, created_at FROM users WHERE email = $1",
         )
         .bind(email)
-        .fetch_optional(pool)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(user)
+    }
+
+    /// 查找用户
+    /// 根据用户ID查找用户
+    ///
+    /// # 参数
+    /// * `id` - 用户ID
+    ///
+    /// # 返回
+    /// * `Result<Option<User>, AppError>` - 成功则返回可能存在的用户实例，失败则返回错误
+    pub async fn find_user_by_id(&self, id: i64) -> Result<Option<User>, AppError> {
+        let user = sqlx::query_as(
+            "SELECT id, ws_id, fullname, email, created_at FROM users WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
         .await?;
         Ok(user)
     }
 
     /// 新建用户
-    /// 创建新用户
+    /// 创建新用户，工作空间不存在时自动创建，首个加入的用户成为工作空间所有者；
+    /// 整个过程在一个事务中完成，并发注册同一邮箱时返回 EmailAlreadyExists
     ///
     /// # 参数
     /// * `input` - 包含用户注册信息的CreateUser结构体
-    /// * `pool` - 数据库连接池
     ///
     /// # 返回
     /// * `Result<Self, AppError>` - 成功则返回新创建的用户实例，失败则返回错误
-    pub async fn create(input: CreateUser, pool: &PgPool) -> Result<Self, AppError> {
-        let password_hash = hash_password(&input.password)?;
-        let db_user = Self::find_by_email(&input.email, pool).await?;
-        if db_user.is_some() {
-            return Err(AppError::EmailAlreadyExists(input.email.clone()));
+    pub async fn create_user(&self, input: CreateUser) -> Result<User, AppError> {
+        if input.workspace.trim().is_empty() {
+            return Err(AppError::InvalidInput(
+                "workspace name cannot be empty".to_string(),
+            ));
         }
-        let user = sqlx::query_as(
+        let password_hash = hash_password(&input.password)?;
+
+        let mut tx = self.pool.begin().await?;
+        let ws = upsert_workspace(&mut tx, &input.workspace).await?;
+        let user: User = sqlx::query_as(
             r#"
             INSERT INTO users (ws_id, email, fullname, password_hash)
             VALUES ($1, $2, $3, $4)
             RETURNING id, ws_id, fullname, email, created_at
             "#,
         )
-        .bind(&input.ws_id)
+        .bind(ws.id)
         .bind(&input.email)
         .bind(&input.full_name)
         .bind(password_hash)
-        .fetch_one(pool)
-        .await?;
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db)
+                if db.code().as_deref() == Some(UNIQUE_VIOLATION)
+                    && db.constraint() == Some(EMAIL_INDEX) =>
+            {
+                AppError::EmailAlreadyExists(input.email.clone())
+            }
+            e => e.into(),
+        })?;
+        if ws.owner_id == 0 {
+            set_workspace_owner(&mut tx, ws.id, user.id).await?;
+        }
+        tx.commit().await?;
         Ok(user)
     }
 
@@ -79,17 +119,15 @@ impl User {
     ///
     /// # 参数
     /// * `input` - 包含用户登录信息的 SignInUser结构体
-    /// * `pool` - 数据库连接池
     ///
     /// # 返回
     /// * `Result<Option<Self>, AppError>` - 成功则返回可能存在的用户实例，失败则返回错误
-    pub async fn verify(input: &SignInUser, pool: &PgPool) -> Result<Option<Self>, AppError> {
+    pub async fn verify_user(&self, input: &SignInUser) -> Result<Option<User>, AppError> {
         let user: Option<User> = sqlx::query_as(
-            "SELECT ws_id, id, fullname, email, password_hash, created_at FROM users WHERE email = $1 and ws_id = $2",
+            "SELECT ws_id, id, fullname, email, password_hash, created_at FROM users WHERE email = $1",
         )
         .bind(&input.email)
-        .bind(&input.ws_id)
-        .fetch_optional(pool)
+        .fetch_optional(&self.pool)
         .await?;
         match user {
             Some(mut user) => {
@@ -111,14 +149,14 @@ impl User {
     ///
     /// # 参数
     /// * `user_id` - 用户ID
-    /// * `pool` - 数据库连接池
     ///
     /// # 返回
     /// * `Result<bool, AppError>` - 成功则返回是否删除成功，失败则返回错误
-    pub async fn delete(user_id: i64, pool: &PgPool) -> Result<bool, AppError> {
+    #[allow(dead_code)]
+    pub async fn delete_user(&self, user_id: i64) -> Result<bool, AppError> {
         let result = sqlx::query("DELETE FROM users WHERE id = $1")
             .bind(user_id)
-            .execute(pool)
+            .execute(&self.pool)
             .await?;
 
         Ok(result.rows_affected() > 0)
@@ -129,14 +167,14 @@ impl User {
     ///
     /// # 参数
     /// * `email` - 用户邮箱地址
-    /// * `pool` - 数据库连接池
     ///
     /// # 返回
     /// * `Result<bool, AppError>` - 成功则返回是否删除成功，失败则返回错误
-    pub async fn delete_by_email(email: &str, pool: &PgPool) -> Result<bool, AppError> {
+    #[allow(dead_code)]
+    pub async fn delete_user_by_email(&self, email: &str) -> Result<bool, AppError> {
         let result = sqlx::query("DELETE FROM users WHERE email = $1")
             .bind(email)
-            .execute(pool)
+            .execute(&self.pool)
             .await?;
 
         Ok(result.rows_affected() > 0)
@@ -171,26 +209,13 @@ fn verify_password(password: &str, password_hash: &str) -> Result<bool, AppError
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::User;
     use anyhow::Result;
-    use sqlx::PgPool;
-
-    // 辅助函数：创建测试数据库连接池
-    async fn create_test_pool() -> Result<PgPool> {
-        // 从.env文件加载环境变量
-        dotenvy::dotenv().ok();
-        // 获取数据库连接URL
-        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-        // 创建数据库连接池
-        let pool = PgPool::connect(&database_url).await?;
-        Ok(pool)
-    }
 
     #[tokio::test]
     async fn test_find_by_email_not_found() -> Result<()> {
-        let pool = create_test_pool().await?;
+        let (_tdb, state) = AppState::new_for_test().await?;
         let email = "nonexistent@example.com".to_string();
-        let user = User::find_by_email(&email, &pool).await?;
+        let user = state.find_user_by_email(&email).await?;
         println!("查找到的用户信息: {:?}", user);
         assert!(user.is_none());
         Ok(())
@@ -198,76 +223,92 @@ mod tests {
 
     #[tokio::test]
     async fn test_find_by_email_found() -> Result<()> {
-        let pool = create_test_pool().await?;
+        let (_tdb, state) = AppState::new_for_test().await?;
 
         let email = "zhangsan@test.com".to_string();
         // 先创建一个用户
         let create_user = CreateUser {
-            ws_id: 1,
             full_name: "Zhang San".to_string(),
             email: email.clone(),
             workspace: "test_workspace".to_string(),
             password: "password123".to_string(),
         };
-        User::create(create_user, &pool).await?;
+        state.create_user(create_user).await?;
 
         // 然后查找这个用户
-        let user = User::find_by_email(&email, &pool).await?;
+        let user = state.find_user_by_email(&email).await?;
         println!("查找到的用户信息: {:?}", user);
         assert!(Some(user).is_some());
-        // 删除该用户
-        let r = User::delete_by_email(&email, &pool).await?;
-        println!("删除用户:{}", &email);
-        assert_eq!(true, r);
         Ok(())
     }
 
     #[tokio::test]
     async fn test_verify_user_success() -> Result<()> {
-        let pool = create_test_pool().await?;
+        let (_tdb, state) = AppState::new_for_test().await?;
 
         // 创建用户
         let create_user = CreateUser {
-            ws_id: 1,
             full_name: "Verify User".to_string(),
             email: "verify@example.com".to_string(),
             workspace: "test_workspace".to_string(),
             password: "correct_password".to_string(),
         };
-        User::create(create_user, &pool).await?;
+        state.create_user(create_user).await?;
 
         // 验证正确密码
         let signin_user = SignInUser {
-            ws_id: 1,
             email: "verify@example.com".to_string(),
             password: "correct_password".to_string(),
         };
 
-        let verified_user = User::verify(&signin_user, &pool).await?;
+        let verified_user = state.verify_user(&signin_user).await?;
         assert!(&verified_user.is_some());
 
         // 提取用户对象（避免多次 unwrap）
         let user = verified_user.unwrap();
         assert_eq!(user.email, "verify@example.com");
 
-        // 修复点：添加 await 和错误处理
-        User::delete(user.id, &pool).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn concurrent_signups_should_conflict() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let signup = |workspace: &str| CreateUser {
+            full_name: "Racer".to_string(),
+            email: "racer@example.com".to_string(),
+            workspace: workspace.to_string(),
+            password: "password123".to_string(),
+        };
+        let (a, b) = tokio::join!(
+            state.create_user(signup("race_ws")),
+            state.create_user(signup("race_ws"))
+        );
+        let (ok, err) = match (a, b) {
+            (Ok(user), Err(e)) | (Err(e), Ok(user)) => (user, e),
+            ret => panic!("expected exactly one signup to succeed: {:?}", ret),
+        };
+        assert!(matches!(err, AppError::EmailAlreadyExists(_)));
+        let ws = state.find_workspace_by_name("race_ws").await?.unwrap();
+        assert_eq!(ws.owner_id, ok.id);
 
+        let ret = state.create_user(signup("other_ws")).await;
+        assert!(matches!(ret, Err(AppError::EmailAlreadyExists(_))));
+        assert!(state.find_workspace_by_name("other_ws").await?.is_none());
         Ok(())
     }
 
     #[tokio::test]
     async fn test_verify_nonexistent_user() -> Result<()> {
-        let pool = create_test_pool().await?;
+        let (_tdb, state) = AppState::new_for_test().await?;
 
         // 尝试验证不存在的用户
         let signin_user = SignInUser {
-            ws_id: 1,
             email: "nonexistent_verify@example.com".to_string(),
             password: "any_password".to_string(),
         };
 
-        let verified_user = User::verify(&signin_user, &pool).await?;
+        let verified_user = state.verify_user(&signin_user).await?;
         assert!(verified_user.is_none());
 
         Ok(())
@@ -294,28 +335,27 @@ mod tests {
 
     #[tokio::test]
     async fn test_delete_user_by_id() -> Result<()> {
-        let pool = create_test_pool().await?;
+        let (_tdb, state) = AppState::new_for_test().await?;
 
         // 先创建一个用户
         let create_user = CreateUser {
-            ws_id: 1,
             full_name: "Delete Test User".to_string(),
             email: "delete_test@example.com".to_string(),
             workspace: "test_workspace".to_string(),
             password: "password123".to_string(),
         };
-        let user = User::create(create_user, &pool).await?;
+        let user = state.create_user(create_user).await?;
 
         // 验证用户存在
-        let found_user = User::find_by_email("delete_test@example.com", &pool).await?;
+        let found_user = state.find_user_by_email("delete_test@example.com").await?;
         assert!(found_user.is_some());
 
         // 删除用户
-        let deleted = User::delete(user.id, &pool).await?;
+        let deleted = state.delete_user(user.id).await?;
         assert!(deleted);
 
         // 验证用户已被删除
-        let not_found_user = User::find_by_email("delete_test@example.com", &pool).await?;
+        let not_found_user = state.find_user_by_email("delete_test@example.com").await?;
         assert!(not_found_user.is_none());
 
         Ok(())
@@ -323,28 +363,33 @@ mod tests {
 
     #[tokio::test]
     async fn test_delete_user_by_email() -> Result<()> {
-        let pool = create_test_pool().await?;
+        let (_tdb, state) = AppState::new_for_test().await?;
 
         // 先创建一个用户
         let create_user = CreateUser {
-            ws_id: 1,
             full_name: "Delete By Email User".to_string(),
             email: "delete_by_email@example.com".to_string(),
             workspace: "test_workspace".to_string(),
             password: "password123".to_string(),
         };
-        User::create(create_user, &pool).await?;
+        state.create_user(create_user).await?;
 
         // 验证用户存在
-        let found_user = User::find_by_email("delete_by_email@example.com", &pool).await?;
+        let found_user = state
+            .find_user_by_email("delete_by_email@example.com")
+            .await?;
         assert!(found_user.is_some());
 
         // 通过邮箱删除用户
-        let deleted = User::delete_by_email("delete_by_email@example.com", &pool).await?;
+        let deleted = state
+            .delete_user_by_email("delete_by_email@example.com")
+            .await?;
         assert!(deleted);
 
         // 验证用户已被删除
-        let not_found_user = User::find_by_email("delete_by_email@example.com", &pool).await?;
+        let not_found_user = state
+            .find_user_by_email("delete_by_email@example.com")
+            .await?;
         assert!(not_found_user.is_none());
 
         Ok(())
@@ -352,10 +397,10 @@ mod tests {
 
     #[tokio::test]
     async fn test_delete_nonexistent_user_by_id() -> Result<()> {
-        let pool = create_test_pool().await?;
+        let (_tdb, state) = AppState::new_for_test().await?;
 
         // 尝试删除不存在的用户ID
-        let deleted = User::delete(99999, &pool).await?;
+        let deleted = state.delete_user(99999).await?;
         assert!(!deleted);
 
         Ok(())
@@ -363,10 +408,12 @@ mod tests {
 
     #[tokio::test]
     async fn test_delete_nonexistent_user_by_email() -> Result<()> {
-        let pool = create_test_pool().await?;
+        let (_tdb, state) = AppState::new_for_test().await?;
 
         // 尝试删除不存在的用户邮箱
-        let deleted = User::delete_by_email("nonexistent@example.com", &pool).await?;
+        let deleted = state
+            .delete_user_by_email("nonexistent@example.com")
+            .await?;
         assert!(!deleted);
 
         Ok(())
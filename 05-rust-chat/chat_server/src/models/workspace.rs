@@ -0,0 +1,122 @@
+use crate::error::AppError;
+use crate::models::{ChatUser, Workspace};
+use crate::AppState;
+use sqlx::PgConnection;
+
+impl AppState {
+    /// 查找工作空间
+    /// 根据名称查找工作空间
+    ///
+    /// # 参数
+    /// * `name` - 工作空间名称
+    ///
+    /// # 返回
+    /// * `Result<Option<Workspace>, AppError>` - 成功则返回可能存在的工作空间实例，失败则返回错误
+    #[allow(dead_code)]
+    pub async fn find_workspace_by_name(&self, name: &str) -> Result<Option<Workspace>, AppError> {
+        let ws =
+            sqlx::query_as("SELECT id, name, owner_id, created_at FROM workspaces WHERE name = $1")
+                .bind(name)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(ws)
+    }
+
+    /// 查询工作空间成员
+    /// 返回工作空间下的所有用户
+    ///
+    /// # 参数
+    /// * `ws_id` - 工作空间ID
+    ///
+    /// # 返回
+    /// * `Result<Vec<ChatUser>, AppError>` - 成功则返回用户列表，失败则返回错误
+    pub async fn fetch_chat_users(&self, ws_id: i64) -> Result<Vec<ChatUser>, AppError> {
+        let users =
+            sqlx::query_as("SELECT id, fullname, email FROM users WHERE ws_id = $1 ORDER BY id")
+                .bind(ws_id)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(users)
+    }
+}
+
+/// 按名称取得工作空间，不存在时新建（尚无所有者，owner_id 为 0）
+/// 在事务中调用时会锁定该工作空间，同一工作空间的注册因此串行执行
+pub(super) async fn upsert_workspace(
+    conn: &mut PgConnection,
+    name: &str,
+) -> Result<Workspace, AppError> {
+    let ws = sqlx::query_as(
+        r#"
+        INSERT INTO workspaces (name)
+        VALUES ($1)
+        ON CONFLICT (name) DO UPDATE SET name = EXCLUDED.name
+        RETURNING id, name, owner_id, created_at
+        "#,
+    )
+    .bind(name)
+    .fetch_one(&mut *conn)
+    .await?;
+    Ok(ws)
+}
+
+/// 设置所有者，仅在工作空间尚无所有者时生效
+pub(super) async fn set_workspace_owner(
+    conn: &mut PgConnection,
+    id: i64,
+    owner_id: i64,
+) -> Result<(), AppError> {
+    sqlx::query("UPDATE workspaces SET owner_id = $1 WHERE id = $2 AND owner_id = 0")
+        .bind(owner_id)
+        .bind(id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::CreateUser;
+    use anyhow::Result;
+
+    #[tokio::test]
+    async fn test_first_user_should_own_workspace() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let ws_name = "ws_owner_test";
+        let input = CreateUser {
+            full_name: "Owner".to_string(),
+            email: "ws_owner@example.com".to_string(),
+            workspace: ws_name.to_string(),
+            password: "password123".to_string(),
+        };
+        let owner = state.create_user(input).await?;
+        let input = CreateUser {
+            full_name: "Member".to_string(),
+            email: "ws_member@example.com".to_string(),
+            workspace: ws_name.to_string(),
+            password: "password123".to_string(),
+        };
+        let member = state.create_user(input).await?;
+        assert_eq!(owner.ws_id, member.ws_id);
+
+        let ws = state.find_workspace_by_name(ws_name).await?.unwrap();
+        assert_eq!(ws.owner_id, owner.id);
+
+        let users = state.fetch_chat_users(ws.id).await?;
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[0].id, owner.id);
+        assert_eq!(users[1].email, "ws_member@example.com");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_find_workspace_not_found() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let ws = state
+            .find_workspace_by_name("nonexistent_workspace")
+            .await?;
+        assert!(ws.is_none());
+        Ok(())
+    }
+}
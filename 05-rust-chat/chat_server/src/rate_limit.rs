@@ -0,0 +1,316 @@
+use crate::{AppError, AppState};
+use axum::extract::{ConnectInfo, Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use chat_core::User;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use tracing::error;
+
+/// 内存中最多保留的令牌桶数量，超过后立即清理一次已经回满的桶
+const MAX_BUCKETS: usize = 10_000;
+
+/// 后台清理过期限流状态的间隔
+const EVICT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 令牌桶配额：桶容量为 `burst`，每分钟补充 `per_minute` 个令牌
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Quota {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+/// 限流状态的存储，默认实现保存在进程内存中，多实例部署时可替换为共享存储
+pub trait RateLimitStore: Send + Sync {
+    /// 从 `key` 对应的令牌桶中取出一个令牌，桶已空时返回还需等待的时间
+    fn acquire(&self, key: &str, quota: Quota) -> Result<(), Duration>;
+
+    /// `key` 处于锁定状态时返回剩余的锁定时间
+    fn locked_for(&self, key: &str) -> Option<Duration>;
+
+    /// 记录一次失败，`lockout` 时间内累计失败 `max_failures` 次后锁定 `lockout`，返回锁定时间
+    fn record_failure(&self, key: &str, max_failures: u32, lockout: Duration) -> Option<Duration>;
+
+    /// 成功后清除失败记录
+    fn clear_failures(&self, key: &str);
+
+    /// 清理已经回满的令牌桶以及已过期的失败记录，由后台任务定期调用
+    fn evict_expired(&self);
+}
+
+/// 进程内存中的限流状态，DashMap 按 key 分片加锁，不同 key 的请求互不阻塞
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    buckets: DashMap<String, Bucket>,
+    failures: DashMap<String, Failures>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    /// 创建该桶的配额，清理时按各自的补充速度判断是否已回满
+    quota: Quota,
+}
+
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    first_at: Instant,
+    locked_until: Option<Instant>,
+    lockout: Duration,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+        let rate = self.quota.per_minute as f64 / 60.0;
+        self.tokens = (self.tokens + elapsed * rate).min(self.quota.burst as f64);
+        self.updated_at = now;
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= self.quota.burst as f64
+    }
+}
+
+impl Failures {
+    fn is_expired(&self, now: Instant) -> bool {
+        match self.locked_until {
+            Some(until) => until <= now,
+            None => now.duration_since(self.first_at) >= self.lockout,
+        }
+    }
+}
+
+impl MemoryStore {
+    /// 回满的桶与不存在的桶等价，可以直接删除
+    fn evict_buckets(&self, now: Instant) {
+        self.buckets.retain(|_, bucket| {
+            bucket.refill(now);
+            !bucket.is_full()
+        });
+    }
+}
+
+impl RateLimitStore for MemoryStore {
+    fn acquire(&self, key: &str, quota: Quota) -> Result<(), Duration> {
+        let now = Instant::now();
+        if self.buckets.len() >= MAX_BUCKETS {
+            self.evict_buckets(now);
+        }
+        let mut bucket = self.buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: quota.burst as f64,
+            updated_at: now,
+            quota,
+        });
+        bucket.refill(now);
+        bucket.quota = quota;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        if quota.per_minute == 0 {
+            return Err(Duration::from_secs(60));
+        }
+        let wait = (1.0 - bucket.tokens) * 60.0 / quota.per_minute as f64;
+        Err(Duration::from_secs_f64(wait))
+    }
+
+    fn locked_for(&self, key: &str) -> Option<Duration> {
+        let now = Instant::now();
+        self.failures
+            .get(key)
+            .and_then(|f| f.locked_until)
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+
+    fn record_failure(&self, key: &str, max_failures: u32, lockout: Duration) -> Option<Duration> {
+        let now = Instant::now();
+        let mut entry = self.failures.entry(key.to_string()).or_insert(Failures {
+            count: 0,
+            first_at: now,
+            locked_until: None,
+            lockout,
+        });
+        // 上一轮的失败记录已过期则重新计数
+        if entry.is_expired(now) {
+            *entry = Failures {
+                count: 0,
+                first_at: now,
+                locked_until: None,
+                lockout,
+            };
+        }
+        entry.count += 1;
+        if entry.count >= max_failures {
+            entry.locked_until = Some(now + lockout);
+            return Some(lockout);
+        }
+        None
+    }
+
+    fn clear_failures(&self, key: &str) {
+        self.failures.remove(key);
+    }
+
+    fn evict_expired(&self) {
+        let now = Instant::now();
+        self.evict_buckets(now);
+        self.failures.retain(|_, f| !f.is_expired(now));
+    }
+}
+
+/// 启动定期清理限流状态的后台任务，`limiter` 被释放后任务随之退出
+pub(crate) fn spawn_evict_expired(limiter: &Arc<dyn RateLimitStore>) {
+    let limiter: Weak<dyn RateLimitStore> = Arc::downgrade(limiter);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(EVICT_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let Some(limiter) = limiter.upgrade() else {
+                break;
+            };
+            limiter.evict_expired();
+        }
+    });
+}
+
+/// 按客户端 IP 限流，用于登录、注册等公开接口
+pub(crate) async fn ip_rate_limit(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Response {
+    // 拿不到客户端地址时所有请求会共用一个桶，宁可拒绝请求也不能让一个客户端挡住所有人
+    let Some(ConnectInfo(addr)) = req.extensions().get::<ConnectInfo<SocketAddr>>().copied() else {
+        error!("client address missing, serve the app with into_make_service_with_connect_info");
+        return AppError::Internal("client address unavailable".to_string()).into_response();
+    };
+    let ip = addr.ip();
+    let quota = state.config.rate_limit.ip;
+    match state.limiter.acquire(&format!("ip:{}", ip), quota) {
+        Ok(()) => next.run(req).await,
+        Err(retry_after) => AppError::TooManyRequests(retry_after).into_response(),
+    }
+}
+
+/// 按用户限流，需放在鉴权层之内，用于发送消息等接口
+pub(crate) async fn user_rate_limit(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(user_id) = req.extensions().get::<User>().map(|user| user.id) else {
+        return next.run(req).await;
+    };
+    let quota = state.config.rate_limit.user;
+    match state.limiter.acquire(&format!("user:{}", user_id), quota) {
+        Ok(()) => next.run(req).await,
+        Err(retry_after) => AppError::TooManyRequests(retry_after).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_bucket_should_limit_burst() {
+        let store = MemoryStore::default();
+        let quota = Quota {
+            burst: 2,
+            per_minute: 60,
+        };
+        assert!(store.acquire("a", quota).is_ok());
+        assert!(store.acquire("a", quota).is_ok());
+        let retry_after = store.acquire("a", quota).unwrap_err();
+        assert!(retry_after <= Duration::from_secs(1));
+        // 不同的 key 互不影响
+        assert!(store.acquire("b", quota).is_ok());
+    }
+
+    #[test]
+    fn evict_should_use_each_bucket_quota() {
+        let store = MemoryStore::default();
+        let slow = Quota {
+            burst: 2,
+            per_minute: 1,
+        };
+        let fast = Quota {
+            burst: 1,
+            per_minute: 60_000,
+        };
+        store.acquire("slow", slow).unwrap();
+        store.acquire("fast", fast).unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+
+        // 快桶已回满可以删除，慢桶还没回满，删掉就等于重置了它的配额
+        store.evict_expired();
+        assert!(store.buckets.contains_key("slow"));
+        assert!(!store.buckets.contains_key("fast"));
+        store.acquire("slow", slow).unwrap();
+        assert!(store.acquire("slow", slow).is_err());
+    }
+
+    #[test]
+    fn failures_should_lock_out() {
+        let store = MemoryStore::default();
+        let lockout = Duration::from_secs(60);
+        assert!(store.record_failure("a", 3, lockout).is_none());
+        assert!(store.record_failure("a", 3, lockout).is_none());
+        assert!(store.locked_for("a").is_none());
+        assert_eq!(store.record_failure("a", 3, lockout), Some(lockout));
+        assert!(store.locked_for("a").is_some());
+
+        store.clear_failures("a");
+        assert!(store.locked_for("a").is_none());
+    }
+
+    #[test]
+    fn expired_failures_should_be_evicted() {
+        let store = MemoryStore::default();
+        assert!(store
+            .record_failure("a", 3, Duration::from_millis(1))
+            .is_none());
+        assert!(store
+            .record_failure("b", 3, Duration::from_secs(60))
+            .is_none());
+        std::thread::sleep(Duration::from_millis(5));
+        store.evict_expired();
+        assert!(!store.failures.contains_key("a"));
+        assert!(store.failures.contains_key("b"));
+    }
+
+    #[tokio::test]
+    async fn ip_rate_limit_should_fail_closed_without_client_address() -> anyhow::Result<()> {
+        use axum::body::Body;
+        use axum::http::StatusCode;
+        use axum::middleware::from_fn_with_state;
+        use axum::routing::get;
+        use axum::Router;
+        use tower::ServiceExt;
+
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(from_fn_with_state(state.clone(), ip_rate_limit))
+            .with_state(state);
+
+        let req = Request::builder().uri("/").body(Body::empty())?;
+        let res = app.clone().oneshot(req).await?;
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let mut req = Request::builder().uri("/").body(Body::empty())?;
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 1234))));
+        let res = app.oneshot(req).await?;
+        assert_eq!(res.status(), StatusCode::OK);
+        Ok(())
+    }
+}
@@ -1 +0,0 @@
-mod jwt;
\ No newline at end of file
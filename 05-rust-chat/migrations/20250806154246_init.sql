@@ -1,6 +1,5 @@
 -- this file is used for postgresql database initialization
 -- create user table
-DROP table if exists users;
 CREATE TABLE IF NOT EXISTS users
 (
     id            bigserial PRIMARY KEY,
@@ -12,12 +11,6 @@ CREATE TABLE IF NOT EXISTS users
     created_at    timestamptz DEFAULT CURRENT_TIMESTAMP
 );
 
--- 插入测试用户数据
-INSERT INTO users (ws_id, fullname, email, password_hash) VALUES
-(1,'张三', 'zhangsan@example.com', '$argon2id$v=19$m=4096,t=3,p=1$waSRM7HJw7xMIxlG$WDpOvt9hXYRjVyYqgyVx3AcE6lS5DE/Jb6TRfs+BA9w'),
-(1,'李四', 'lisi@example.com', '$argon2id$v=19$m=4096,t=3,p=1$waSRM7HJw7xMIxlG$WDpOvt9hXYRjVyYqgyVx3AcE6lS5DE/Jb6TRfs+BA9w'),
-(1,'王五', 'wangwu@example.com', '$argon2id$v=19$m=4096,t=3,p=1$waSRM7HJw7xMIxlG$WDpOvt9hXYRjVyYqgyVx3AcE6lS5DE/Jb6TRfs+BA9w');
-
 -- create index for users for email
 CREATE UNIQUE INDEX IF NOT EXISTS email_index ON users (email);
 
@@ -30,12 +23,19 @@ CREATE UNIQUE INDEX IF NOT EXISTS email_index ON users (email);
 
 
 -- create chat type: single, group, private_channel, public_channel
-CREATE TYPE chat_type AS ENUM (
-    'single',
-    'group',
-    'private_channel',
-    'public_channel'
-    );
+DO
+$$
+BEGIN
+    CREATE TYPE chat_type AS ENUM (
+        'single',
+        'group',
+        'private_channel',
+        'public_channel'
+        );
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END
+$$;
 
 -- create chat table
 CREATE TABLE IF NOT EXISTS chats
@@ -0,0 +1,18 @@
+-- chats: single/group chats have no name, channels are owned by their creator
+ALTER TABLE chats ALTER COLUMN name DROP NOT NULL;
+ALTER TABLE chats ADD COLUMN IF NOT EXISTS owner_id bigint REFERENCES users (id);
+DO
+$$
+BEGIN
+    IF EXISTS (SELECT 1
+               FROM information_schema.columns
+               WHERE table_name = 'chats'
+                 AND column_name = 'created_at'
+                 AND data_type = 'timestamp without time zone') THEN
+        ALTER TABLE chats ALTER COLUMN created_at TYPE timestamptz;
+    END IF;
+END
+$$;
+
+-- create index for chats for members, used to list chats of a user
+CREATE INDEX IF NOT EXISTS chat_members_index ON chats USING GIN (members);
@@ -0,0 +1,8 @@
+-- messages: images defaults to an empty list, created_at carries a time zone
+UPDATE messages SET images = '{}' WHERE images IS NULL;
+ALTER TABLE messages ALTER COLUMN images SET DEFAULT '{}';
+ALTER TABLE messages ALTER COLUMN images SET NOT NULL;
+ALTER TABLE messages ALTER COLUMN created_at TYPE timestamptz;
+
+-- create index for messages for chat_id and id desc, used by keyset pagination
+CREATE INDEX IF NOT EXISTS chat_id_id_index ON messages (chat_id, id DESC);
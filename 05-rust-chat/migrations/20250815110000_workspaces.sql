@@ -0,0 +1,28 @@
+-- create workspace table, owner_id is set to the first user who joins
+CREATE TABLE IF NOT EXISTS workspaces
+(
+    id         bigserial PRIMARY KEY,
+    name       varchar(32) NOT NULL UNIQUE,
+    owner_id   bigint      NOT NULL DEFAULT 0,
+    created_at timestamptz DEFAULT CURRENT_TIMESTAMP
+);
+
+-- existing users belong to the default workspace
+INSERT INTO workspaces (id, name, owner_id)
+SELECT 1, 'default', min(id) FROM users WHERE ws_id = 1 OR ws_id IS NULL HAVING count(*) > 0
+ON CONFLICT (id) DO NOTHING;
+SELECT setval('workspaces_id_seq', max(id)) FROM workspaces HAVING count(*) > 0;
+
+UPDATE users SET ws_id = 1 WHERE ws_id IS NULL;
+ALTER TABLE users ALTER COLUMN ws_id SET NOT NULL;
+DO
+$$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_constraint WHERE conname = 'users_ws_id_fkey') THEN
+        ALTER TABLE users ADD CONSTRAINT users_ws_id_fkey FOREIGN KEY (ws_id) REFERENCES workspaces (id);
+    END IF;
+END
+$$;
+
+-- create index for users for ws_id
+CREATE INDEX IF NOT EXISTS ws_id_index ON users (ws_id);
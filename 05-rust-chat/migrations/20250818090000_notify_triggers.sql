@@ -0,0 +1,38 @@
+-- notify chat changes: channel chat_updated, payload {op, old, new}
+CREATE OR REPLACE FUNCTION notify_chat_updated()
+    RETURNS TRIGGER AS
+$$
+BEGIN
+    PERFORM pg_notify('chat_updated',
+                      json_build_object('op', TG_OP, 'old', OLD, 'new', NEW)::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS chat_updated_trigger ON chats;
+CREATE TRIGGER chat_updated_trigger
+    AFTER INSERT OR UPDATE OR DELETE
+    ON chats
+    FOR EACH ROW
+EXECUTE FUNCTION notify_chat_updated();
+
+-- notify new messages: channel chat_message_created, payload {message, members}
+CREATE OR REPLACE FUNCTION notify_chat_message_created()
+    RETURNS TRIGGER AS
+$$
+DECLARE
+    chat_members bigint[];
+BEGIN
+    SELECT members INTO chat_members FROM chats WHERE id = NEW.chat_id;
+    PERFORM pg_notify('chat_message_created',
+                      json_build_object('message', NEW, 'members', chat_members)::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS chat_message_created_trigger ON messages;
+CREATE TRIGGER chat_message_created_trigger
+    AFTER INSERT
+    ON messages
+    FOR EACH ROW
+EXECUTE FUNCTION notify_chat_message_created();
@@ -0,0 +1,18 @@
+-- refresh tokens, only the sha256 hash of the token is stored; a token is deleted once used (rotation)
+CREATE TABLE IF NOT EXISTS refresh_tokens
+(
+    id         bigserial PRIMARY KEY,
+    user_id    bigint      NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    token_hash char(64)    NOT NULL UNIQUE,
+    expires_at timestamptz NOT NULL,
+    created_at timestamptz DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS refresh_tokens_user_id_index ON refresh_tokens (user_id);
+
+-- revoked access tokens (jti denylist), rows can be purged once expires_at has passed
+CREATE TABLE IF NOT EXISTS revoked_tokens
+(
+    jti        varchar(64) PRIMARY KEY,
+    expires_at timestamptz NOT NULL
+);
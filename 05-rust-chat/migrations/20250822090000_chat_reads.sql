@@ -0,0 +1,35 @@
+-- read receipts: the last message each member has read in a chat, only ever moves forward
+CREATE TABLE IF NOT EXISTS chat_reads
+(
+    chat_id              bigint      NOT NULL REFERENCES chats (id) ON DELETE CASCADE,
+    user_id              bigint      NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    last_read_message_id bigint      NOT NULL,
+    updated_at           timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (chat_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS chat_reads_user_id_index ON chat_reads (user_id);
+
+-- notify read receipts: channel chat_message_read, payload {read, members}
+CREATE OR REPLACE FUNCTION notify_chat_message_read()
+    RETURNS TRIGGER AS
+$$
+DECLARE
+    chat_members bigint[];
+BEGIN
+    IF TG_OP = 'UPDATE' AND OLD.last_read_message_id = NEW.last_read_message_id THEN
+        RETURN NULL;
+    END IF;
+    SELECT members INTO chat_members FROM chats WHERE id = NEW.chat_id;
+    PERFORM pg_notify('chat_message_read',
+                      json_build_object('read', NEW, 'members', chat_members)::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS chat_message_read_trigger ON chat_reads;
+CREATE TRIGGER chat_message_read_trigger
+    AFTER INSERT OR UPDATE
+    ON chat_reads
+    FOR EACH ROW
+EXECUTE FUNCTION notify_chat_message_read();
@@ -0,0 +1,70 @@
+-- message editing and soft deletion: a deleted message keeps its row as a tombstone with empty content
+ALTER TABLE messages
+    ADD COLUMN IF NOT EXISTS edited_at  timestamptz,
+    ADD COLUMN IF NOT EXISTS deleted_at timestamptz;
+
+-- emoji reactions, one row per (message, user, emoji)
+CREATE TABLE IF NOT EXISTS message_reactions
+(
+    message_id bigint      NOT NULL REFERENCES messages (id) ON DELETE CASCADE,
+    user_id    bigint      NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    emoji      varchar(32) NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (message_id, user_id, emoji)
+);
+
+-- notify edited / deleted messages: channel chat_message_updated, payload {message, members}
+CREATE OR REPLACE FUNCTION notify_chat_message_updated()
+    RETURNS TRIGGER AS
+$$
+DECLARE
+    chat_members bigint[];
+BEGIN
+    SELECT members INTO chat_members FROM chats WHERE id = NEW.chat_id;
+    PERFORM pg_notify('chat_message_updated',
+                      json_build_object('message', NEW, 'members', chat_members)::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS chat_message_updated_trigger ON messages;
+CREATE TRIGGER chat_message_updated_trigger
+    AFTER UPDATE
+    ON messages
+    FOR EACH ROW
+EXECUTE FUNCTION notify_chat_message_updated();
+
+-- notify reactions: channel chat_message_reaction, payload {op, reaction, members}
+CREATE OR REPLACE FUNCTION notify_chat_message_reaction()
+    RETURNS TRIGGER AS
+$$
+DECLARE
+    reaction     message_reactions;
+    chat_members bigint[];
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        reaction := OLD;
+    ELSE
+        reaction := NEW;
+    END IF;
+    SELECT c.members
+    INTO chat_members
+    FROM messages m
+             JOIN chats c ON c.id = m.chat_id
+    WHERE m.id = reaction.message_id;
+    -- the message is gone when reactions are removed by a cascading delete
+    IF chat_members IS NOT NULL THEN
+        PERFORM pg_notify('chat_message_reaction',
+                          json_build_object('op', TG_OP, 'reaction', reaction, 'members',
+                                            chat_members)::text);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS chat_message_reaction_trigger ON message_reactions;
+CREATE TRIGGER chat_message_reaction_trigger
+    AFTER INSERT OR DELETE
+    ON message_reactions
+    FOR EACH ROW
+EXECUTE FUNCTION notify_chat_message_reaction();
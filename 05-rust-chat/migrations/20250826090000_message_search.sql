@@ -0,0 +1,41 @@
+-- full-text search over message content; the simple configuration keeps tokens as-is so it works for any language
+ALTER TABLE messages
+    ADD COLUMN IF NOT EXISTS content_tsv tsvector
+        GENERATED ALWAYS AS (to_tsvector('simple', content)) STORED;
+
+CREATE INDEX IF NOT EXISTS messages_content_tsv_index ON messages USING GIN (content_tsv);
+
+-- message json sent by the notify triggers, an explicit column list keeps content_tsv out of the payload
+CREATE OR REPLACE FUNCTION message_payload(m messages)
+    RETURNS json AS
+$$
+SELECT json_build_object('id', m.id, 'chat_id', m.chat_id, 'sender_id', m.sender_id,
+                         'content', m.content, 'images', m.images, 'created_at', m.created_at,
+                         'edited_at', m.edited_at, 'deleted_at', m.deleted_at);
+$$ LANGUAGE sql IMMUTABLE;
+
+CREATE OR REPLACE FUNCTION notify_chat_message_created()
+    RETURNS TRIGGER AS
+$$
+DECLARE
+    chat_members bigint[];
+BEGIN
+    SELECT members INTO chat_members FROM chats WHERE id = NEW.chat_id;
+    PERFORM pg_notify('chat_message_created',
+                      json_build_object('message', message_payload(NEW), 'members', chat_members)::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION notify_chat_message_updated()
+    RETURNS TRIGGER AS
+$$
+DECLARE
+    chat_members bigint[];
+BEGIN
+    SELECT members INTO chat_members FROM chats WHERE id = NEW.chat_id;
+    PERFORM pg_notify('chat_message_updated',
+                      json_build_object('message', message_payload(NEW), 'members', chat_members)::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
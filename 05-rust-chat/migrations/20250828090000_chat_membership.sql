@@ -0,0 +1,15 @@
+-- channel admins, a subset of members who can manage membership besides the owner
+ALTER TABLE chats
+    ADD COLUMN IF NOT EXISTS admins bigint[] NOT NULL DEFAULT '{}';
+
+-- invitation links for private channels
+CREATE TABLE IF NOT EXISTS chat_invitations
+(
+    code       varchar(64) PRIMARY KEY,
+    chat_id    bigint      NOT NULL REFERENCES chats (id) ON DELETE CASCADE,
+    created_by bigint      NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    expires_at timestamptz NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS chat_invitations_chat_id_index ON chat_invitations (chat_id);
@@ -0,0 +1,241 @@
+-- normalize chat membership: chat_members is the source of truth,
+-- chats.members / chats.admins stay as arrays maintained by a trigger so existing readers keep working
+DO
+$$
+BEGIN
+    CREATE TYPE chat_role AS ENUM ('owner', 'admin', 'member');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END
+$$;
+
+CREATE TABLE IF NOT EXISTS chat_members
+(
+    chat_id     bigint      NOT NULL REFERENCES chats (id) ON DELETE CASCADE,
+    user_id     bigint      NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    role        chat_role   NOT NULL DEFAULT 'member',
+    joined_at   timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    muted_until timestamptz,
+    PRIMARY KEY (chat_id, user_id)
+);
+
+-- "which chats is user X in"
+CREATE INDEX IF NOT EXISTS chat_members_user_id_index ON chat_members (user_id);
+
+-- move existing members over
+INSERT INTO chat_members (chat_id, user_id, role, joined_at)
+SELECT c.id,
+       m.user_id,
+       CASE
+           WHEN m.user_id = c.owner_id THEN 'owner'
+           WHEN m.user_id = ANY (c.admins) THEN 'admin'
+           ELSE 'member'
+           END::chat_role,
+       COALESCE(c.created_at, CURRENT_TIMESTAMP)
+FROM chats c,
+     unnest(c.members) AS m(user_id)
+ON CONFLICT DO NOTHING;
+
+-- chats are created before their members are inserted
+ALTER TABLE chats ALTER COLUMN members SET DEFAULT '{}';
+
+-- member ids of a chat, used by the notify triggers
+CREATE OR REPLACE FUNCTION chat_member_ids(id bigint)
+    RETURNS bigint[] AS
+$$
+SELECT COALESCE(array_agg(user_id ORDER BY user_id), '{}')
+FROM chat_members
+WHERE chat_id = id;
+$$ LANGUAGE sql STABLE;
+
+-- keep chats.members / chats.admins in sync with chat_members, ordered by user id
+CREATE OR REPLACE FUNCTION sync_chat_member_arrays()
+    RETURNS TRIGGER AS
+$$
+DECLARE
+    target_id bigint;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        target_id := OLD.chat_id;
+    ELSE
+        target_id := NEW.chat_id;
+    END IF;
+    UPDATE chats c
+    SET members = chat_member_ids(target_id),
+        admins  = COALESCE((SELECT array_agg(user_id ORDER BY user_id)
+                            FROM chat_members
+                            WHERE chat_id = target_id
+                              AND role = 'admin'), '{}')
+    WHERE c.id = target_id;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS chat_member_arrays_trigger ON chat_members;
+CREATE TRIGGER chat_member_arrays_trigger
+    AFTER INSERT OR UPDATE OR DELETE
+    ON chat_members
+    FOR EACH ROW
+EXECUTE FUNCTION sync_chat_member_arrays();
+
+-- mark chats created in the current transaction, their members are announced by chat_updated instead of chat_member_updated
+CREATE OR REPLACE FUNCTION mark_chat_created()
+    RETURNS TRIGGER AS
+$$
+BEGIN
+    PERFORM set_config('chat.created_' || NEW.id, 'true', true);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS chat_created_mark_trigger ON chats;
+CREATE TRIGGER chat_created_mark_trigger
+    AFTER INSERT
+    ON chats
+    FOR EACH ROW
+EXECUTE FUNCTION mark_chat_created();
+
+-- notify chat changes: channel chat_updated, payload {op, old, new}
+-- new chats are announced at commit time so that the members inserted in the same transaction are included,
+-- updates only when a field other than the member arrays changes
+CREATE OR REPLACE FUNCTION notify_chat_updated()
+    RETURNS TRIGGER AS
+$$
+DECLARE
+    chat chats;
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        SELECT * INTO chat FROM chats WHERE id = NEW.id;
+        IF FOUND THEN
+            PERFORM pg_notify('chat_updated',
+                              json_build_object('op', TG_OP, 'old', NULL, 'new', chat)::text);
+        END IF;
+    ELSIF TG_OP = 'UPDATE' THEN
+        PERFORM pg_notify('chat_updated',
+                          json_build_object('op', TG_OP, 'old', OLD, 'new', NEW)::text);
+    ELSE
+        PERFORM pg_notify('chat_updated',
+                          json_build_object('op', TG_OP, 'old', OLD, 'new', NULL)::text);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS chat_created_trigger ON chats;
+CREATE CONSTRAINT TRIGGER chat_created_trigger
+    AFTER INSERT
+    ON chats
+    DEFERRABLE INITIALLY DEFERRED
+    FOR EACH ROW
+EXECUTE FUNCTION notify_chat_updated();
+
+DROP TRIGGER IF EXISTS chat_updated_trigger ON chats;
+CREATE TRIGGER chat_updated_trigger
+    AFTER UPDATE
+    ON chats
+    FOR EACH ROW
+    WHEN ((OLD.name, OLD.type, OLD.owner_id) IS DISTINCT FROM (NEW.name, NEW.type, NEW.owner_id))
+EXECUTE FUNCTION notify_chat_updated();
+
+-- the row still carries the member arrays, the cascading member deletes do not change it
+DROP TRIGGER IF EXISTS chat_deleted_trigger ON chats;
+CREATE TRIGGER chat_deleted_trigger
+    AFTER DELETE
+    ON chats
+    FOR EACH ROW
+EXECUTE FUNCTION notify_chat_updated();
+
+-- notify membership changes: channel chat_member_updated, payload {op, user_id, chat}
+-- skipped for members of a chat created in the same transaction (covered by chat_updated)
+-- and for members removed together with their chat
+CREATE OR REPLACE FUNCTION notify_chat_member_updated()
+    RETURNS TRIGGER AS
+$$
+DECLARE
+    member chat_members;
+    chat   chats;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        member := OLD;
+    ELSE
+        member := NEW;
+    END IF;
+    IF current_setting('chat.created_' || member.chat_id, true) = 'true' THEN
+        RETURN NULL;
+    END IF;
+    SELECT * INTO chat FROM chats WHERE id = member.chat_id;
+    IF NOT FOUND THEN
+        RETURN NULL;
+    END IF;
+    PERFORM pg_notify('chat_member_updated',
+                      json_build_object('op', TG_OP, 'user_id', member.user_id, 'chat', chat)::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS chat_member_updated_trigger ON chat_members;
+CREATE CONSTRAINT TRIGGER chat_member_updated_trigger
+    AFTER INSERT OR DELETE
+    ON chat_members
+    DEFERRABLE INITIALLY DEFERRED
+    FOR EACH ROW
+EXECUTE FUNCTION notify_chat_member_updated();
+
+-- the remaining notify triggers read members from chat_members
+CREATE OR REPLACE FUNCTION notify_chat_message_created()
+    RETURNS TRIGGER AS
+$$
+BEGIN
+    PERFORM pg_notify('chat_message_created',
+                      json_build_object('message', message_payload(NEW), 'members',
+                                        chat_member_ids(NEW.chat_id))::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION notify_chat_message_read()
+    RETURNS TRIGGER AS
+$$
+BEGIN
+    IF TG_OP = 'UPDATE' AND OLD.last_read_message_id = NEW.last_read_message_id THEN
+        RETURN NULL;
+    END IF;
+    PERFORM pg_notify('chat_message_read',
+                      json_build_object('read', NEW, 'members', chat_member_ids(NEW.chat_id))::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION notify_chat_message_updated()
+    RETURNS TRIGGER AS
+$$
+BEGIN
+    PERFORM pg_notify('chat_message_updated',
+                      json_build_object('message', message_payload(NEW), 'members',
+                                        chat_member_ids(NEW.chat_id))::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION notify_chat_message_reaction()
+    RETURNS TRIGGER AS
+$$
+DECLARE
+    reaction message_reactions;
+    chat_id  bigint;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        reaction := OLD;
+    ELSE
+        reaction := NEW;
+    END IF;
+    SELECT m.chat_id INTO chat_id FROM messages m WHERE m.id = reaction.message_id;
+    -- the message is gone when reactions are removed by a cascading delete
+    IF chat_id IS NOT NULL THEN
+        PERFORM pg_notify('chat_message_reaction',
+                          json_build_object('op', TG_OP, 'reaction', reaction, 'members',
+                                            chat_member_ids(chat_id))::text);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
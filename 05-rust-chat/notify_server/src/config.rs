@@ -0,0 +1,256 @@
+use anyhow::{bail, Result};
+use chat_core::{LayeredConfig, TelemetryConfig};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::path::PathBuf;
+
+/// 可被 `NOTIFY__SECTION__KEY` 形式的环境变量覆盖的配置前缀
+const ENV_PREFIX: &str = "NOTIFY__";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppConfig {
+    pub server: ServerConfig,
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub sse: SseConfig,
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerConfig {
+    pub port: u16,
+    pub db_url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthConfig {
+    /// 校验 token 所用公钥（PEM）的文件路径
+    #[serde(default)]
+    pub pk_path: Option<PathBuf>,
+    /// chat_server 的 JWKS 地址（如 http://localhost:8080/.well-known/jwks.json），
+    /// 配置后优先从该地址获取公钥，并定期刷新以跟上密钥轮换
+    #[serde(default)]
+    pub jwks_url: Option<String>,
+    /// JWKS 刷新间隔（秒）
+    #[serde(default = "default_jwks_refresh_secs")]
+    pub jwks_refresh_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SseConfig {
+    /// keep-alive 消息的发送间隔（秒）
+    #[serde(default = "default_keep_alive_secs")]
+    pub keep_alive_secs: u64,
+    /// 每个用户广播通道的容量，客户端落后超过该数量的事件会被丢弃
+    #[serde(default = "default_channel_capacity")]
+    pub channel_capacity: usize,
+}
+
+impl Default for SseConfig {
+    fn default() -> Self {
+        Self {
+            keep_alive_secs: default_keep_alive_secs(),
+            channel_capacity: default_channel_capacity(),
+        }
+    }
+}
+
+fn default_keep_alive_secs() -> u64 {
+    1
+}
+
+fn default_channel_capacity() -> usize {
+    256
+}
+
+fn default_jwks_refresh_secs() -> u64 {
+    300
+}
+
+impl AppConfig {
+    /// 加载配置
+    /// 按 默认值 < ./notify.yml 或 /etc/config/notify.yml < NOTIFY_CONFIG 指定的文件 <
+    /// `NOTIFY__SERVER__PORT` 形式的环境变量 的优先级逐层合并，与 chat_server 使用同一套规则
+    ///
+    /// # 返回
+    /// * `Result<Self>` - 成功则返回最终生效的配置，失败则返回指明出错配置项的错误
+    pub fn load() -> Result<Self> {
+        let config = LayeredConfig::load(
+            defaults(),
+            &["notify.yml", "/etc/config/notify.yml"],
+            "NOTIFY_CONFIG",
+            ENV_PREFIX,
+        )?;
+        Self::from_config(&config)
+    }
+
+    /// 合并配置
+    ///
+    /// # 参数
+    /// * `layers` - 按优先级从低到高排列的配置文件内容
+    /// * `vars` - 环境变量，只有以 `NOTIFY__` 开头的会生效
+    ///
+    /// # 返回
+    /// * `Result<Self>` - 成功则返回合并后的配置，失败则返回错误
+    #[cfg(test)]
+    fn from_layers(
+        layers: impl IntoIterator<Item = Value>,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self> {
+        Self::from_config(&LayeredConfig::from_layers(
+            defaults(),
+            layers,
+            vars,
+            ENV_PREFIX,
+        ))
+    }
+
+    fn from_config(merged: &LayeredConfig) -> Result<Self> {
+        let config = Self {
+            server: ServerConfig {
+                port: merged.field("server.port")?,
+                db_url: merged.field("server.db_url")?,
+            },
+            auth: AuthConfig {
+                pk_path: merged.optional_field("auth.pk_path")?,
+                jwks_url: merged.optional_field("auth.jwks_url")?,
+                jwks_refresh_secs: merged.field("auth.jwks_refresh_secs")?,
+            },
+            sse: SseConfig {
+                keep_alive_secs: merged.field("sse.keep_alive_secs")?,
+                channel_capacity: merged.field("sse.channel_capacity")?,
+            },
+            telemetry: TelemetryConfig {
+                log_level: merged.field("telemetry.log_level")?,
+                log_format: merged.field("telemetry.log_format")?,
+                otlp_endpoint: merged.optional_field("telemetry.otlp_endpoint")?,
+            },
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.auth.pk_path.is_none() && self.auth.jwks_url.is_none() {
+            bail!("either auth.pk_path or auth.jwks_url must be set");
+        }
+        if self.auth.jwks_url.is_some() && self.auth.jwks_refresh_secs == 0 {
+            bail!("auth.jwks_refresh_secs must be greater than 0");
+        }
+        if self.sse.keep_alive_secs == 0 {
+            bail!("sse.keep_alive_secs must be greater than 0");
+        }
+        if self.sse.channel_capacity == 0 {
+            bail!("sse.channel_capacity must be greater than 0");
+        }
+        Ok(())
+    }
+}
+
+fn defaults() -> Value {
+    let mut value: Value = serde_yaml::from_str(
+        r#"
+        server:
+          port: 6687
+        "#,
+    )
+    .expect("default config must be valid yaml");
+    value["auth"]["jwks_refresh_secs"] = Value::from(default_jwks_refresh_secs());
+    value["sse"] = serde_yaml::to_value(SseConfig::default()).expect("sse config is serializable");
+    value["telemetry"] =
+        serde_yaml::to_value(TelemetryConfig::default()).expect("telemetry config is serializable");
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chat_core::LogFormat;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn notify_yml_should_parse() -> Result<()> {
+        let config: AppConfig = serde_yaml::from_str(include_str!("../../notify.yml"))?;
+        assert_eq!(config.server.port, 6687);
+        assert_eq!(config.sse.channel_capacity, 256);
+        config.validate()?;
+        Ok(())
+    }
+
+    #[test]
+    fn env_should_override_config() -> Result<()> {
+        let notify_yml: Value = serde_yaml::from_str(include_str!("../../notify.yml"))?;
+        let config = AppConfig::from_layers(
+            [notify_yml.clone()],
+            vars(&[
+                ("NOTIFY__SERVER__PORT", "7788"),
+                ("NOTIFY__AUTH__PK_PATH", "/run/secrets/decoding.pem"),
+                ("NOTIFY__SSE__CHANNEL_CAPACITY", "16"),
+                ("NOTIFY__TELEMETRY__LOG_FORMAT", "json"),
+                ("NOTIFY_PORT", "ignored"),
+            ]),
+        )?;
+        assert_eq!(config.server.port, 7788);
+        assert_eq!(
+            config.auth.pk_path,
+            Some(PathBuf::from("/run/secrets/decoding.pem"))
+        );
+        assert_eq!(config.sse.channel_capacity, 16);
+        assert_eq!(config.sse.keep_alive_secs, 1);
+        assert_eq!(config.telemetry.log_format, LogFormat::Json);
+        assert_eq!(config.telemetry.otlp_endpoint, None);
+
+        let err = AppConfig::from_layers([notify_yml], vars(&[("NOTIFY__SERVER__PORT", "abc")]))
+            .unwrap_err();
+        assert!(err.to_string().contains("server.port"));
+        Ok(())
+    }
+
+    #[test]
+    fn defaults_should_fill_missing_keys() -> Result<()> {
+        let config = AppConfig::from_layers(
+            [],
+            vars(&[
+                ("NOTIFY__SERVER__DB_URL", "postgres://localhost/chat"),
+                (
+                    "NOTIFY__AUTH__JWKS_URL",
+                    "http://localhost:8080/.well-known/jwks.json",
+                ),
+            ]),
+        )?;
+        assert_eq!(config.server.port, 6687);
+        assert_eq!(config.auth.jwks_refresh_secs, 300);
+        assert_eq!(config.sse.channel_capacity, 256);
+        assert_eq!(config.telemetry, TelemetryConfig::default());
+
+        let err = AppConfig::from_layers([], vec![]).unwrap_err();
+        assert!(err.to_string().contains("NOTIFY__SERVER__DB_URL"));
+        Ok(())
+    }
+
+    #[test]
+    fn zero_channel_capacity_should_fail() -> Result<()> {
+        let mut config: AppConfig = serde_yaml::from_str(include_str!("../../notify.yml"))?;
+        config.sse.channel_capacity = 0;
+        assert!(config.validate().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn missing_verification_key_should_fail() -> Result<()> {
+        let mut config: AppConfig = serde_yaml::from_str(include_str!("../../notify.yml"))?;
+        config.auth.pk_path = None;
+        assert!(config.validate().is_err());
+
+        config.auth.jwks_url = Some("http://localhost:8080/.well-known/jwks.json".to_string());
+        config.validate()?;
+        Ok(())
+    }
+}
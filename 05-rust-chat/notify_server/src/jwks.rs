@@ -0,0 +1,80 @@
+use crate::AppState;
+use anyhow::{bail, Result};
+use axum::body::Bytes;
+use chat_core::utils::{DecodingKey, Jwks};
+use http_body_util::{BodyExt, Empty};
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// 获取公钥集合
+/// 从 chat_server 的 `/.well-known/jwks.json` 获取当前有效的校验公钥，仅支持 http
+///
+/// # 参数
+/// * `url` - JWKS 地址
+///
+/// # 返回
+/// * `Result<DecodingKey>` - 成功则返回公钥集合，失败则返回错误
+pub(crate) async fn fetch_jwks(url: &str) -> Result<DecodingKey> {
+    let client = Client::builder(TokioExecutor::new()).build_http::<Empty<Bytes>>();
+    let res = client.get(url.parse()?).await?;
+    if !res.status().is_success() {
+        bail!("fetch jwks from {} failed: {}", url, res.status());
+    }
+    let body = res.into_body().collect().await?.to_bytes();
+    let jwks: Jwks = serde_json::from_slice(&body)?;
+    DecodingKey::from_jwks(&jwks)
+}
+
+/// 定期刷新公钥集合，获取失败时保留原有公钥
+pub(crate) fn spawn_jwks_refresh(state: AppState, url: String, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // 第一次 tick 立即返回，启动时已经获取过一次
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            match fetch_jwks(&url).await {
+                Ok(dk) => {
+                    info!(
+                        "refreshed {} verification keys from {}",
+                        dk.jwks().keys.len(),
+                        url
+                    );
+                    *state.dk.write().expect("dk lock poisoned") = dk;
+                }
+                Err(e) => warn!("refresh jwks from {} failed: {}", url, e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn fetch_jwks_should_work() -> Result<()> {
+        let dk = DecodingKey::load(include_str!("../../chat_core/fixtures/decoding.pem"))?;
+        let jwks = dk.jwks();
+        let app = Router::new().route(
+            "/.well-known/jwks.json",
+            get(move || async move { Json(jwks) }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let url = format!("http://{}/.well-known/jwks.json", addr);
+        let fetched = fetch_jwks(&url).await?;
+        assert_eq!(fetched.jwks(), dk.jwks());
+
+        let url = format!("http://{}/missing", addr);
+        assert!(fetch_jwks(&url).await.is_err());
+        Ok(())
+    }
+}
@@ -1,20 +1,241 @@
+mod config;
+mod jwks;
+mod metrics;
+mod notif;
+mod presence;
 mod sse;
 
+use std::{fmt, ops::Deref, sync::Arc, sync::RwLock, time::Duration};
+
 use axum::{
+    async_trait,
+    middleware::from_fn_with_state,
     response::{Html, IntoResponse},
     routing::get,
     Router,
 };
+use chat_core::metrics::track_metrics;
+use chat_core::middlewares::{set_layer, verify_token, verify_token_or_query, TokenVerify};
+use chat_core::utils::{DecodingKey, TokenClaims};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use jwks::{fetch_jwks, spawn_jwks_refresh};
+use metrics::{healthz_handler, metrics_handler, readyz_handler, NotifyMetrics};
+use notif::setup_pg_listener;
+use presence::{presence_handler, PresenceMap};
+use sqlx::PgPool;
 use sse::sse_handler;
+use tokio::sync::broadcast;
+
+pub use config::AppConfig;
+pub use notif::AppEvent;
 
 const INDEX_HTML: &str = include_str!("../index.html");
 
-pub fn get_router() -> Router {
-    Router::new()
+/// 已连接的用户：user_id => 该用户所有 SSE 连接共享的广播通道
+pub(crate) type UserMap = Arc<DashMap<i64, broadcast::Sender<Arc<AppEvent>>>>;
+
+#[derive(Clone)]
+pub(crate) struct AppState {
+    inner: Arc<AppStateInner>,
+}
+
+pub(crate) struct AppStateInner {
+    pub(crate) config: AppConfig,
+    pub(crate) users: UserMap,
+    pub(crate) presence: PresenceMap,
+    /// 正在输入的信号：(chat_id, user_id) => 过期时间
+    pub(crate) typing: DashMap<(i64, i64), DateTime<Utc>>,
+    /// 配置了 jwks_url 时会被后台任务定期替换
+    pub(crate) dk: RwLock<DecodingKey>,
+    pub(crate) metrics: Arc<NotifyMetrics>,
+    /// 与 chat_server 共用的数据库，用于查询已吊销的 token
+    pub(crate) pool: PgPool,
+}
+
+impl Deref for AppState {
+    type Target = AppStateInner;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl fmt::Debug for AppState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppState")
+            .field("users", &self.users.len())
+            .finish()
+    }
+}
+
+impl AppState {
+    /// 创建应用状态，数据库连接在首次查询时才建立
+    pub fn new(config: AppConfig, dk: DecodingKey) -> anyhow::Result<Self> {
+        let pool = PgPool::connect_lazy(&config.server.db_url)?;
+        Ok(Self {
+            inner: Arc::new(AppStateInner {
+                config,
+                users: Arc::new(DashMap::new()),
+                presence: Arc::new(DashMap::new()),
+                typing: DashMap::new(),
+                dk: RwLock::new(dk),
+                metrics: Arc::new(NotifyMetrics::default()),
+                pool,
+            }),
+        })
+    }
+
+    /// 查询 access token 的 jti 是否已被 chat_server 吊销（登出或修改密码）
+    ///
+    /// # 参数
+    /// * `jti` - access token 的 jti
+    ///
+    /// # 返回
+    /// * `Result<bool>` - 已吊销返回 true，查询失败则返回错误
+    async fn is_token_revoked(&self, jti: &str) -> anyhow::Result<bool> {
+        let (revoked,): (bool,) =
+            sqlx::query_as("SELECT EXISTS(SELECT 1 FROM revoked_tokens WHERE jti = $1)")
+                .bind(jti)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(revoked)
+    }
+}
+
+#[async_trait]
+impl TokenVerify for AppState {
+    type Error = anyhow::Error;
+
+    async fn verify(&self, token: &str) -> Result<TokenClaims, Self::Error> {
+        let claims = self.dk.read().expect("dk lock poisoned").decode(token)?;
+        if self.is_token_revoked(&claims.jti).await? {
+            anyhow::bail!("token has been revoked");
+        }
+        Ok(claims)
+    }
+}
+
+pub async fn get_router(config: AppConfig) -> anyhow::Result<Router> {
+    let dk = match (&config.auth.jwks_url, &config.auth.pk_path) {
+        (Some(url), _) => fetch_jwks(url).await?,
+        (None, Some(path)) => DecodingKey::load(&std::fs::read_to_string(path)?)?,
+        (None, None) => anyhow::bail!("either auth.pk_path or auth.jwks_url must be set"),
+    };
+    let state = AppState::new(config, dk)?;
+    if let Some(url) = &state.config.auth.jwks_url {
+        let interval = Duration::from_secs(state.config.auth.jwks_refresh_secs);
+        spawn_jwks_refresh(state.clone(), url.clone(), interval);
+    }
+    setup_pg_listener(&state.config.server.db_url, state.clone()).await?;
+    Ok(router(state))
+}
+
+fn router(state: AppState) -> Router {
+    // EventSource 无法设置请求头，只有 /events 接受查询参数中的 token
+    let app = Router::new()
+        .route("/presence", get(presence_handler))
+        .layer(from_fn_with_state(state.clone(), verify_token::<AppState>))
+        .route(
+            "/events",
+            get(sse_handler).layer(from_fn_with_state(
+                state.clone(),
+                verify_token_or_query::<AppState>,
+            )),
+        )
         .route("/", get(index_handler))
-        .route("/events", get(sse_handler))
+        .route_layer(from_fn_with_state(
+            state.metrics.http.clone(),
+            track_metrics,
+        ));
+    let app = set_layer(app);
+
+    // 探针与指标接口不计入请求统计，也不输出请求日志
+    let probes = Router::new()
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler))
+        .route("/metrics", get(metrics_handler));
+
+    app.merge(probes).with_state(state)
 }
 
 async fn index_handler() -> impl IntoResponse {
     Html(INDEX_HTML)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+impl AppState {
+    /// 构造测试用的 AppState
+    /// 在 .env 中 DATABASE_URL 指向的 Postgres 上创建独立的临时数据库，
+    /// 返回的 TestPg 离开作用域时删除该数据库
+    pub async fn new_for_test() -> anyhow::Result<(chat_core::test_util::TestPg, Self)> {
+        dotenvy::dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let tdb = chat_core::test_util::TestPg::new(&database_url).await?;
+        let mut config: AppConfig = serde_yaml::from_str(include_str!("../../notify.yml"))?;
+        config.server.db_url = tdb.url();
+        let dk = DecodingKey::load(include_str!("../../chat_core/fixtures/decoding.pem"))?;
+        let state = Self::new(config, dk)?;
+        Ok((tdb, state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use chat_core::utils::EncodingKey;
+    use chat_core::User;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn revoked_token_should_be_rejected() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let ek = EncodingKey::load(include_str!("../../chat_core/fixtures/encoding.pem"))?;
+        let app = router(state.clone());
+
+        let user = User {
+            id: 1,
+            ws_id: 1,
+            fullname: "张三".to_string(),
+            email: "zhangsan@example.com".to_string(),
+            password_hash: None,
+            created_at: Utc::now(),
+        };
+        let token = ek.sign(user)?;
+        let events = |token: &str| {
+            Request::builder()
+                .uri(format!("/events?access_token={}", token))
+                .body(Body::empty())
+        };
+
+        let res = app.clone().oneshot(events(&token)?).await?;
+        assert_eq!(res.status(), StatusCode::OK);
+        drop(res);
+
+        // 只有 /events 接受查询参数中的 token
+        let req = Request::builder()
+            .uri(format!("/presence?access_token={}", token))
+            .body(Body::empty())?;
+        let res = app.clone().oneshot(req).await?;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let jti = state
+            .dk
+            .read()
+            .expect("dk lock poisoned")
+            .decode(&token)?
+            .jti;
+        sqlx::query(
+            "INSERT INTO revoked_tokens (jti, expires_at) VALUES ($1, now() + interval '1 hour')",
+        )
+        .bind(&jti)
+        .execute(&state.pool)
+        .await?;
+        let res = app.oneshot(events(&token)?).await?;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        Ok(())
+    }
+}
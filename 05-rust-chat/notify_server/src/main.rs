@@ -1,23 +1,19 @@
-mod sse;
-
 use anyhow::Result;
-use notify_server::get_router;
+use notify_server::{get_router, AppConfig};
 use tokio::net::TcpListener;
-use tracing::{info, level_filters::LevelFilter};
-use tracing_subscriber::{fmt::Layer, layer::SubscriberExt, util::SubscriberInitExt, Layer as _};
+use tracing::info;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let layer = Layer::new().with_filter(LevelFilter::INFO);
-    tracing_subscriber::registry().with(layer).init();
-
-    let addr = "0.0.0.0:6687";
+    let config = AppConfig::load()?;
+    let _guard = chat_core::init_tracing("notify-server", &config.telemetry)?;
+    let addr = format!("0.0.0.0:{}", config.server.port);
 
-    let app = get_router();
+    let app = get_router(config).await?;
     let listener = TcpListener::bind(&addr).await?;
     info!("Listening on: {}", addr);
 
     axum::serve(listener, app.into_make_service()).await?;
 
     Ok(())
-}
\ No newline at end of file
+}
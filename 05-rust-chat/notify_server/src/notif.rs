@@ -0,0 +1,517 @@
+use crate::metrics::NotifyMetrics;
+use crate::presence::Presence;
+use crate::AppState;
+use anyhow::Result;
+use chat_core::{Chat, ChatRead, Message, MessageReaction};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// 推送给客户端的事件，序列化后的 `event` 字段即 SSE 的事件名
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "event")]
+pub enum AppEvent {
+    NewChat(Chat),
+    ChatUpdated(Chat),
+    AddToChat(Chat),
+    RemoveFromChat(Chat),
+    NewMessage(Message),
+    MessageRead(ChatRead),
+    MessageUpdated(Message),
+    MessageDeleted(Message),
+    ReactionAdded(MessageReaction),
+    ReactionRemoved(MessageReaction),
+    Typing(Typing),
+    TypingStopped(Typing),
+    Presence(Presence),
+}
+
+impl AppEvent {
+    /// SSE 的事件名
+    pub fn name(&self) -> &'static str {
+        match self {
+            AppEvent::NewChat(_) => "NewChat",
+            AppEvent::ChatUpdated(_) => "ChatUpdated",
+            AppEvent::AddToChat(_) => "AddToChat",
+            AppEvent::RemoveFromChat(_) => "RemoveFromChat",
+            AppEvent::NewMessage(_) => "NewMessage",
+            AppEvent::MessageRead(_) => "MessageRead",
+            AppEvent::MessageUpdated(_) => "MessageUpdated",
+            AppEvent::MessageDeleted(_) => "MessageDeleted",
+            AppEvent::ReactionAdded(_) => "ReactionAdded",
+            AppEvent::ReactionRemoved(_) => "ReactionRemoved",
+            AppEvent::Typing(_) => "Typing",
+            AppEvent::TypingStopped(_) => "TypingStopped",
+            AppEvent::Presence(_) => "Presence",
+        }
+    }
+}
+
+/// 正在输入的信号只在内存中转发，超过该时间（秒）未续期则自动过期
+const TYPING_TTL_SECS: i64 = 5;
+
+/// 正在输入的信号，客户端应在 `expires_at` 之后自动清除
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Typing {
+    pub chat_id: i64,
+    pub user_id: i64,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// 一次数据库通知解析出的事件及其接收者
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub user_ids: HashSet<i64>,
+    pub event: Arc<AppEvent>,
+}
+
+// pg_notify('chat_updated', ...) 的负载
+#[derive(Debug, Deserialize)]
+struct ChatChanged {
+    op: String,
+    old: Option<Chat>,
+    new: Option<Chat>,
+}
+
+// pg_notify('chat_member_updated', ...) 的负载，成员加入或被移除时只通知该成员
+#[derive(Debug, Deserialize)]
+struct ChatMemberUpdated {
+    op: String,
+    user_id: i64,
+    chat: Chat,
+}
+
+// pg_notify('chat_message_created', ...) 的负载
+#[derive(Debug, Deserialize)]
+struct ChatMessageCreated {
+    message: Message,
+    members: Vec<i64>,
+}
+
+// pg_notify('chat_message_read', ...) 的负载
+#[derive(Debug, Deserialize)]
+struct ChatMessageRead {
+    read: ChatRead,
+    members: Vec<i64>,
+}
+
+// pg_notify('chat_message_reaction', ...) 的负载
+#[derive(Debug, Deserialize)]
+struct ChatMessageReaction {
+    op: String,
+    reaction: MessageReaction,
+    members: Vec<i64>,
+}
+
+// pg_notify('chat_typing', ...) 的负载，由 chat_server 直接发出，不落库
+#[derive(Debug, Deserialize)]
+struct ChatTyping {
+    chat_id: i64,
+    user_id: i64,
+    members: Vec<i64>,
+}
+
+/// 监听的数据库通知频道
+const CHANNELS: [&str; 7] = [
+    "chat_updated",
+    "chat_member_updated",
+    "chat_message_created",
+    "chat_message_read",
+    "chat_message_updated",
+    "chat_message_reaction",
+    "chat_typing",
+];
+
+/// 监听连接重连的初始等待时间，每次失败翻倍，直到 RECONNECT_MAX_BACKOFF
+const RECONNECT_MIN_BACKOFF: Duration = Duration::from_millis(500);
+const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// 监听 chats / chat_members / messages / chat_reads / message_reactions 表的变更通知以及正在输入的信号，解析为事件后只推送给相关的已连接用户
+/// 首次连接失败时返回错误，之后连接断开会在后台按退避间隔重连并重新监听，不会停止推送
+pub async fn setup_pg_listener(db_url: &str, state: AppState) -> Result<()> {
+    let listener = connect_listener(db_url).await?;
+    state.metrics.set_listener_connected(true);
+    tokio::spawn(run_listener(listener, db_url.to_string(), state));
+    Ok(())
+}
+
+async fn connect_listener(db_url: &str) -> Result<PgListener> {
+    let mut listener = PgListener::connect(db_url).await?;
+    listener.listen_all(CHANNELS).await?;
+    Ok(listener)
+}
+
+async fn run_listener(mut listener: PgListener, db_url: String, state: AppState) {
+    let _guard = ListenerGuard(state.metrics.clone());
+    loop {
+        // 连接被断开时 recv 会先尝试自动重连，重连失败才返回错误
+        let notif = match listener.recv().await {
+            Ok(notif) => notif,
+            Err(e) => {
+                warn!("receive pg notification failed: {}, reconnecting", e);
+                state.metrics.set_listener_connected(false);
+                listener = reconnect_listener(&db_url).await;
+                state.metrics.set_listener_connected(true);
+                continue;
+            }
+        };
+        info!("receive notification on {}", notif.channel());
+        let notifications = match Notification::load(notif.channel(), notif.payload()) {
+            Ok(notifications) => notifications,
+            Err(e) => {
+                warn!("parse notification failed: {}", e);
+                continue;
+            }
+        };
+        for notification in notifications {
+            dispatch(&state, &notification);
+            if let AppEvent::Typing(typing) = notification.event.as_ref() {
+                schedule_typing_expiry(&state, typing.clone(), notification.user_ids);
+            }
+        }
+    }
+}
+
+/// 监听任务因 panic 等原因退出时标记监听已停止，使就绪与存活检查失败
+struct ListenerGuard(Arc<NotifyMetrics>);
+
+impl Drop for ListenerGuard {
+    fn drop(&mut self) {
+        warn!("pg listener task exited");
+        self.0.set_listener_exited();
+    }
+}
+
+/// 按指数退避重新建立监听连接，直到成功
+async fn reconnect_listener(db_url: &str) -> PgListener {
+    let mut backoff = RECONNECT_MIN_BACKOFF;
+    loop {
+        tokio::time::sleep(backoff).await;
+        match connect_listener(db_url).await {
+            Ok(listener) => {
+                info!("pg listener reconnected");
+                return listener;
+            }
+            Err(e) => {
+                warn!(
+                    "reconnect pg listener failed: {}, retry in {:?}",
+                    e, backoff
+                );
+                backoff = (backoff * 2).min(RECONNECT_MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+/// 将事件推送给所有接收者中已连接的用户
+fn dispatch(state: &AppState, notification: &Notification) {
+    let users = &state.users;
+    state.metrics.event_dispatched(notification.event.name());
+    for user_id in &notification.user_ids {
+        let Some(tx) = users.get(user_id).map(|tx| tx.clone()) else {
+            continue;
+        };
+        if tx.send(notification.event.clone()).is_err() {
+            // 所有连接都已断开，清理该用户
+            info!("user {} disconnected, remove it", user_id);
+            users.remove_if(user_id, |_, tx| tx.receiver_count() == 0);
+        }
+    }
+}
+
+/// 正在输入的信号到期且没有被新的信号续期时，向同一批接收者推送 TypingStopped
+fn schedule_typing_expiry(state: &AppState, typing: Typing, user_ids: HashSet<i64>) {
+    let key = (typing.chat_id, typing.user_id);
+    state.typing.insert(key, typing.expires_at);
+    let state = state.clone();
+    tokio::spawn(async move {
+        let ttl = (typing.expires_at - Utc::now())
+            .to_std()
+            .unwrap_or(Duration::ZERO);
+        tokio::time::sleep(ttl).await;
+        let expires_at = typing.expires_at;
+        if state
+            .typing
+            .remove_if(&key, |_, at| *at == expires_at)
+            .is_some()
+        {
+            let notification = Notification::new(user_ids, AppEvent::TypingStopped(typing));
+            dispatch(&state, &notification);
+        }
+    });
+}
+
+impl Notification {
+    fn new(user_ids: impl IntoIterator<Item = i64>, event: AppEvent) -> Self {
+        Self {
+            user_ids: user_ids.into_iter().collect(),
+            event: Arc::new(event),
+        }
+    }
+
+    /// 根据通知频道和负载生成事件，聊天改名等变化推送给全体成员，
+    /// 成员变化时新成员收到 AddToChat，被移除的成员收到 RemoveFromChat
+    pub fn load(channel: &str, payload: &str) -> Result<Vec<Self>> {
+        let notifications = match channel {
+            "chat_updated" => {
+                let payload: ChatChanged = serde_json::from_str(payload)?;
+                match (payload.op.as_str(), payload.old, payload.new) {
+                    ("INSERT", _, Some(new)) => {
+                        vec![Self::new(new.members.clone(), AppEvent::NewChat(new))]
+                    }
+                    ("UPDATE", _, Some(new)) => {
+                        vec![Self::new(new.members.clone(), AppEvent::ChatUpdated(new))]
+                    }
+                    ("DELETE", Some(old), _) => {
+                        vec![Self::new(
+                            old.members.clone(),
+                            AppEvent::RemoveFromChat(old),
+                        )]
+                    }
+                    (op, _, _) => anyhow::bail!("unexpected chat_updated op: {}", op),
+                }
+            }
+            "chat_member_updated" => {
+                let payload: ChatMemberUpdated = serde_json::from_str(payload)?;
+                let event = match payload.op.as_str() {
+                    "INSERT" => AppEvent::AddToChat(payload.chat),
+                    "DELETE" => AppEvent::RemoveFromChat(payload.chat),
+                    op => anyhow::bail!("unexpected chat_member_updated op: {}", op),
+                };
+                vec![Self::new([payload.user_id], event)]
+            }
+            "chat_message_created" => {
+                let payload: ChatMessageCreated = serde_json::from_str(payload)?;
+                vec![Self::new(
+                    payload.members,
+                    AppEvent::NewMessage(payload.message),
+                )]
+            }
+            "chat_message_read" => {
+                let payload: ChatMessageRead = serde_json::from_str(payload)?;
+                vec![Self::new(
+                    payload.members,
+                    AppEvent::MessageRead(payload.read),
+                )]
+            }
+            "chat_message_updated" => {
+                // 复用新消息的负载格式，软删除的消息带有 deleted_at
+                let payload: ChatMessageCreated = serde_json::from_str(payload)?;
+                let event = if payload.message.deleted_at.is_some() {
+                    AppEvent::MessageDeleted(payload.message)
+                } else {
+                    AppEvent::MessageUpdated(payload.message)
+                };
+                vec![Self::new(payload.members, event)]
+            }
+            "chat_message_reaction" => {
+                let payload: ChatMessageReaction = serde_json::from_str(payload)?;
+                let event = match payload.op.as_str() {
+                    "INSERT" => AppEvent::ReactionAdded(payload.reaction),
+                    "DELETE" => AppEvent::ReactionRemoved(payload.reaction),
+                    op => anyhow::bail!("unexpected chat_message_reaction op: {}", op),
+                };
+                vec![Self::new(payload.members, event)]
+            }
+            "chat_typing" => {
+                let payload: ChatTyping = serde_json::from_str(payload)?;
+                let typing = Typing {
+                    chat_id: payload.chat_id,
+                    user_id: payload.user_id,
+                    expires_at: Utc::now() + chrono::Duration::seconds(TYPING_TTL_SECS),
+                };
+                // 不回推给正在输入的用户自己
+                let members = payload
+                    .members
+                    .into_iter()
+                    .filter(|id| *id != payload.user_id);
+                vec![Self::new(members, AppEvent::Typing(typing))]
+            }
+            _ => anyhow::bail!("unknown notification channel: {}", channel),
+        };
+        Ok(notifications)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHAT_NEW: &str = r#"{"id":1,"name":null,"type":"group","members":[1,2,4],"owner_id":null,"created_at":"2025-08-18T09:00:00.123456+00:00"}"#;
+
+    #[test]
+    fn load_new_chat_should_work() -> Result<()> {
+        let payload = format!(r#"{{"op":"INSERT","old":null,"new":{}}}"#, CHAT_NEW);
+        let notifications = Notification::load("chat_updated", &payload)?;
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].user_ids, HashSet::from([1, 2, 4]));
+        assert!(matches!(*notifications[0].event, AppEvent::NewChat(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn load_chat_updated_should_notify_all_members() -> Result<()> {
+        let payload = format!(
+            r#"{{"op":"UPDATE","old":{},"new":{}}}"#,
+            CHAT_NEW,
+            CHAT_NEW.replace(r#""name":null"#, r#""name":"team""#)
+        );
+        let notifications = Notification::load("chat_updated", &payload)?;
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].user_ids, HashSet::from([1, 2, 4]));
+        let AppEvent::ChatUpdated(chat) = &*notifications[0].event else {
+            panic!("expected ChatUpdated");
+        };
+        assert_eq!(chat.name.as_deref(), Some("team"));
+        Ok(())
+    }
+
+    #[test]
+    fn load_chat_member_updated_should_work() -> Result<()> {
+        let payload = format!(r#"{{"op":"INSERT","user_id":4,"chat":{}}}"#, CHAT_NEW);
+        let notifications = Notification::load("chat_member_updated", &payload)?;
+        assert_eq!(notifications[0].user_ids, HashSet::from([4]));
+        assert!(matches!(*notifications[0].event, AppEvent::AddToChat(_)));
+
+        let payload = format!(r#"{{"op":"DELETE","user_id":3,"chat":{}}}"#, CHAT_NEW);
+        let notifications = Notification::load("chat_member_updated", &payload)?;
+        assert_eq!(notifications[0].user_ids, HashSet::from([3]));
+        assert!(matches!(
+            *notifications[0].event,
+            AppEvent::RemoveFromChat(_)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn load_new_message_should_work() -> Result<()> {
+        let payload = r#"{"message":{"id":1,"chat_id":1,"sender_id":1,"content":"hello","images":[],"created_at":"2025-08-18T09:00:00.123456+00:00"},"members":[1,2]}"#;
+        let notifications = Notification::load("chat_message_created", payload)?;
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].user_ids, HashSet::from([1, 2]));
+        assert!(matches!(*notifications[0].event, AppEvent::NewMessage(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn load_message_read_should_work() -> Result<()> {
+        let payload = r#"{"read":{"chat_id":1,"user_id":2,"last_read_message_id":10,"updated_at":"2025-08-22T09:00:00.123456+00:00"},"members":[1,2]}"#;
+        let notifications = Notification::load("chat_message_read", payload)?;
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].user_ids, HashSet::from([1, 2]));
+        assert!(matches!(*notifications[0].event, AppEvent::MessageRead(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn load_message_updated_should_work() -> Result<()> {
+        let payload = r#"{"message":{"id":1,"chat_id":1,"sender_id":1,"content":"hi","images":[],"created_at":"2025-08-24T09:00:00.123456+00:00","edited_at":"2025-08-24T09:01:00.123456+00:00","deleted_at":null},"members":[1,2]}"#;
+        let notifications = Notification::load("chat_message_updated", payload)?;
+        assert!(matches!(
+            *notifications[0].event,
+            AppEvent::MessageUpdated(_)
+        ));
+
+        let payload = r#"{"message":{"id":1,"chat_id":1,"sender_id":1,"content":"","images":[],"created_at":"2025-08-24T09:00:00.123456+00:00","edited_at":null,"deleted_at":"2025-08-24T09:02:00.123456+00:00"},"members":[1,2]}"#;
+        let notifications = Notification::load("chat_message_updated", payload)?;
+        assert_eq!(notifications[0].user_ids, HashSet::from([1, 2]));
+        assert!(matches!(
+            *notifications[0].event,
+            AppEvent::MessageDeleted(_)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn load_reaction_should_work() -> Result<()> {
+        let reaction = r#"{"message_id":1,"user_id":2,"emoji":"👍","created_at":"2025-08-24T09:00:00.123456+00:00"}"#;
+        let payload = format!(
+            r#"{{"op":"INSERT","reaction":{},"members":[1,2]}}"#,
+            reaction
+        );
+        let notifications = Notification::load("chat_message_reaction", &payload)?;
+        assert!(matches!(
+            *notifications[0].event,
+            AppEvent::ReactionAdded(_)
+        ));
+
+        let payload = format!(
+            r#"{{"op":"DELETE","reaction":{},"members":[1,2]}}"#,
+            reaction
+        );
+        let notifications = Notification::load("chat_message_reaction", &payload)?;
+        assert!(matches!(
+            *notifications[0].event,
+            AppEvent::ReactionRemoved(_)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn load_typing_should_work() -> Result<()> {
+        let payload = r#"{"chat_id":1,"user_id":2,"members":[1,2,3]}"#;
+        let notifications = Notification::load("chat_typing", payload)?;
+        assert_eq!(notifications[0].user_ids, HashSet::from([1, 3]));
+        let AppEvent::Typing(typing) = notifications[0].event.as_ref() else {
+            panic!("expect typing event");
+        };
+        assert_eq!((typing.chat_id, typing.user_id), (1, 2));
+        assert!(typing.expires_at > Utc::now());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn listener_should_survive_backend_termination() -> Result<()> {
+        use axum::response::IntoResponse;
+        use sqlx::PgPool;
+        use tokio::sync::broadcast;
+
+        let (tdb, state) = AppState::new_for_test().await?;
+        let database_url = tdb.url();
+        // 用 application_name 找到本测试的监听连接
+        let app_name = format!("notify_listener_test_{}", std::process::id());
+        let separator = if database_url.contains('?') { '&' } else { '?' };
+        let listener_url = format!("{}{}application_name={}", database_url, separator, app_name);
+
+        let (tx, mut rx) = broadcast::channel(16);
+        state.users.insert(2, tx);
+        setup_pg_listener(&listener_url, state.clone()).await?;
+
+        let pool = PgPool::connect(&database_url).await?;
+        let terminated: Vec<(bool,)> = sqlx::query_as(
+            "SELECT pg_terminate_backend(pid) FROM pg_stat_activity WHERE application_name = $1",
+        )
+        .bind(&app_name)
+        .fetch_all(&pool)
+        .await?;
+        assert_eq!(terminated, vec![(true,)]);
+
+        // 重连前发出的通知会丢失，因此反复发送直到收到
+        let payload = r#"{"chat_id":1,"user_id":1,"members":[1,2]}"#;
+        let event = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                sqlx::query("SELECT pg_notify('chat_typing', $1)")
+                    .bind(payload)
+                    .execute(&pool)
+                    .await?;
+                if let Ok(Ok(event)) =
+                    tokio::time::timeout(Duration::from_millis(200), rx.recv()).await
+                {
+                    return anyhow::Ok(event);
+                }
+            }
+        })
+        .await??;
+        assert!(matches!(event.as_ref(), AppEvent::Typing(t) if t.user_id == 1));
+        assert!(crate::metrics::readyz_handler(axum::extract::State(state))
+            .await
+            .into_response()
+            .status()
+            .is_success());
+        Ok(())
+    }
+}
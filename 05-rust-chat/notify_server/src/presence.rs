@@ -0,0 +1,184 @@
+use crate::notif::AppEvent;
+use crate::{AppState, UserMap};
+use axum::extract::State;
+use axum::response::IntoResponse;
+use axum::{Extension, Json};
+use chat_core::User;
+use chrono::{DateTime, Utc};
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// 用户在线状态：user_id => 状态，只保存在内存中，服务重启后重新统计
+pub(crate) type PresenceMap = Arc<DashMap<i64, Presence>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Presence {
+    pub user_id: i64,
+    #[serde(skip)]
+    pub ws_id: i64,
+    pub online: bool,
+    /// 最近一次断开连接的时间，在线时为空
+    pub last_seen: Option<DateTime<Utc>>,
+}
+
+/// 查询当前用户所在工作空间中其他用户的在线状态
+pub(crate) async fn presence_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    let mut presences: Vec<_> = state
+        .presence
+        .iter()
+        .filter(|p| p.ws_id == user.ws_id && p.user_id != user.id)
+        .map(|p| p.clone())
+        .collect();
+    presences.sort_by_key(|p| p.user_id);
+    Json(presences)
+}
+
+/// 用户新建连接：订阅该用户共享的广播通道并标记上线，状态发生变化时通知同一工作空间中的在线用户
+/// 在持有该用户 UserMap 条目锁时更新在线状态，与 [`disconnect`] 互斥，避免重连与下线交错
+pub(crate) fn connect(
+    users: &UserMap,
+    presence: &PresenceMap,
+    user: &User,
+    capacity: usize,
+) -> broadcast::Receiver<Arc<AppEvent>> {
+    let (rx, changed) = {
+        let tx = users
+            .entry(user.id)
+            .or_insert_with(|| broadcast::channel(capacity).0);
+        (tx.subscribe(), mark_online(presence, user))
+    };
+    if changed {
+        broadcast(users, presence, user.id);
+    }
+    rx
+}
+
+/// 用户的一个连接断开：若已没有其他连接则从 UserMap 中移除并标记下线，记录 last_seen 并通知同一工作空间中的在线用户
+/// 与 [`connect`] 一样在条目锁内更新在线状态
+///
+/// # 返回
+/// * `bool` - 该用户是否已没有任何连接
+pub(crate) fn disconnect(users: &UserMap, presence: &PresenceMap, user_id: i64) -> bool {
+    let changed = match users.entry(user_id) {
+        Entry::Occupied(entry) if entry.get().receiver_count() > 0 => return false,
+        Entry::Occupied(entry) => {
+            let changed = mark_offline(presence, user_id);
+            entry.remove();
+            changed
+        }
+        // 推送失败时条目可能已被清理，此时同样没有任何连接
+        Entry::Vacant(_) => mark_offline(presence, user_id),
+    };
+    if changed {
+        broadcast(users, presence, user_id);
+    }
+    true
+}
+
+fn mark_online(presence: &PresenceMap, user: &User) -> bool {
+    let mut entry = presence.entry(user.id).or_insert_with(|| Presence {
+        user_id: user.id,
+        ws_id: user.ws_id,
+        online: false,
+        last_seen: None,
+    });
+    let changed = !entry.online;
+    entry.online = true;
+    entry.last_seen = None;
+    changed
+}
+
+fn mark_offline(presence: &PresenceMap, user_id: i64) -> bool {
+    match presence.get_mut(&user_id) {
+        Some(mut entry) if entry.online => {
+            entry.online = false;
+            entry.last_seen = Some(Utc::now());
+            true
+        }
+        _ => false,
+    }
+}
+
+fn broadcast(users: &UserMap, presence: &PresenceMap, user_id: i64) {
+    // 先复制出需要的数据，避免持有 DashMap 的锁时再访问同一个分片
+    let Some(current) = presence.get(&user_id).map(|p| p.clone()) else {
+        return;
+    };
+    let recipients: Vec<i64> = presence
+        .iter()
+        .filter(|p| p.online && p.ws_id == current.ws_id && p.user_id != user_id)
+        .map(|p| p.user_id)
+        .collect();
+    let event = Arc::new(AppEvent::Presence(current));
+    for id in recipients {
+        if let Some(tx) = users.get(&id) {
+            let _ = tx.send(event.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_user(id: i64, ws_id: i64) -> User {
+        User {
+            id,
+            ws_id,
+            fullname: format!("user {}", id),
+            email: format!("user{}@example.com", id),
+            password_hash: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn presence_should_notify_same_workspace() {
+        let users: UserMap = Arc::new(DashMap::new());
+        let presence: PresenceMap = Arc::new(DashMap::new());
+        let mut rx1 = connect(&users, &presence, &test_user(1, 1), 16);
+        let mut rx3 = connect(&users, &presence, &test_user(3, 2), 16);
+
+        let rx2 = connect(&users, &presence, &test_user(2, 1), 16);
+        let event = rx1.try_recv().unwrap();
+        assert!(matches!(&*event, AppEvent::Presence(p) if p.user_id == 2 && p.online));
+        // 其他工作空间的用户收不到
+        assert!(rx3.try_recv().is_err());
+
+        // 重复上线不再通知
+        let rx2_again = connect(&users, &presence, &test_user(2, 1), 16);
+        assert!(rx1.try_recv().is_err());
+
+        // 仍有其他连接时保持在线
+        drop(rx2);
+        assert!(!disconnect(&users, &presence, 2));
+        assert!(rx1.try_recv().is_err());
+        assert!(presence.get(&2).unwrap().online);
+
+        drop(rx2_again);
+        assert!(disconnect(&users, &presence, 2));
+        assert!(!users.contains_key(&2));
+        let event = rx1.try_recv().unwrap();
+        assert!(
+            matches!(&*event, AppEvent::Presence(p) if p.user_id == 2 && !p.online && p.last_seen.is_some())
+        );
+    }
+
+    #[test]
+    fn disconnect_after_cleanup_should_mark_offline() {
+        let users: UserMap = Arc::new(DashMap::new());
+        let presence: PresenceMap = Arc::new(DashMap::new());
+        let rx = connect(&users, &presence, &test_user(1, 1), 16);
+        drop(rx);
+        // 推送失败时 dispatch 已经移除了该用户
+        users.remove(&1);
+        assert!(disconnect(&users, &presence, 1));
+        assert!(!presence.get(&1).unwrap().online);
+    }
+}
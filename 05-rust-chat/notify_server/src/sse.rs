@@ -1,25 +1,146 @@
+use crate::metrics::NotifyMetrics;
+use crate::presence::{connect, disconnect, PresenceMap};
+use crate::{AppState, UserMap};
+use axum::extract::State;
 use axum::response::{sse::Event, Sse};
-use axum_extra::{headers, TypedHeader};
-use futures::{stream, Stream};
+use axum::Extension;
+use chat_core::User;
+use futures::Stream;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
 use std::{convert::Infallible, time::Duration};
+use tokio_stream::wrappers::BroadcastStream;
 use tokio_stream::StreamExt;
 use tracing::info;
+
 pub(crate) async fn sse_handler(
-    TypedHeader(user_agent): TypedHeader<headers::UserAgent>,
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
-    info!("`{}` connected", user_agent.as_str());
+    info!("user {} connected", user.id);
+
+    // 同一用户的多个连接共享一个广播通道
+    let sse = &state.config.sse;
+    let rx = connect(&state.users, &state.presence, &user, sse.channel_capacity);
 
-    // A `Stream` that repeats an event every second
-    //
-    // You can also create streams from tokio channels using the wrappers in
-    // https://docs.rs/tokio-stream
-    let stream = stream::repeat_with(|| Event::default().data("hi!"))
-        .map(Ok)
-        .throttle(Duration::from_secs(1));
+    // 落后太多被丢弃的事件直接跳过
+    let stream = BroadcastStream::new(rx).filter_map(|event| {
+        let event = event.ok()?;
+        let data = serde_json::to_string(event.as_ref()).ok()?;
+        Some(Ok(Event::default().data(data).event(event.name())))
+    });
+    let stream = UserStream::new(
+        user.id,
+        state.users.clone(),
+        state.presence.clone(),
+        state.metrics.clone(),
+        stream,
+    );
 
     Sse::new(stream).keep_alive(
         axum::response::sse::KeepAlive::new()
-            .interval(Duration::from_secs(1))
+            .interval(Duration::from_secs(sse.keep_alive_secs))
             .text("keep-alive-text"),
     )
-}
\ No newline at end of file
+}
+
+/// 包装用户的事件流，连接断开（流被 drop）时若该用户已没有其他连接则从 UserMap 中移除并标记为下线
+struct UserStream<S> {
+    user_id: i64,
+    users: UserMap,
+    presence: PresenceMap,
+    metrics: Arc<NotifyMetrics>,
+    inner: Option<S>,
+}
+
+impl<S> UserStream<S> {
+    fn new(
+        user_id: i64,
+        users: UserMap,
+        presence: PresenceMap,
+        metrics: Arc<NotifyMetrics>,
+        inner: S,
+    ) -> Self {
+        metrics.sse_connected();
+        Self {
+            user_id,
+            users,
+            presence,
+            metrics,
+            inner: Some(inner),
+        }
+    }
+}
+
+impl<S: Stream + Unpin> Stream for UserStream<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.inner.as_mut() {
+            Some(inner) => Pin::new(inner).poll_next(cx),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+impl<S> Drop for UserStream<S> {
+    fn drop(&mut self) {
+        // 先释放内部的 receiver，再检查是否还有其他连接
+        self.inner.take();
+        self.metrics.sse_disconnected();
+        if disconnect(&self.users, &self.presence, self.user_id) {
+            info!("user {} disconnected", self.user_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notif::AppEvent;
+    use dashmap::DashMap;
+
+    fn subscribe(
+        users: &UserMap,
+        presence: &PresenceMap,
+        metrics: &Arc<NotifyMetrics>,
+        user_id: i64,
+    ) -> UserStream<BroadcastStream<Arc<AppEvent>>> {
+        let user = User {
+            id: user_id,
+            ws_id: 1,
+            fullname: format!("user {}", user_id),
+            email: format!("user{}@example.com", user_id),
+            password_hash: None,
+            created_at: chrono::Utc::now(),
+        };
+        let rx = connect(users, presence, &user, 16);
+        UserStream::new(
+            user_id,
+            users.clone(),
+            presence.clone(),
+            metrics.clone(),
+            BroadcastStream::new(rx),
+        )
+    }
+
+    #[test]
+    fn user_stream_drop_should_cleanup_user() {
+        let users: UserMap = Arc::new(DashMap::new());
+        let presence: PresenceMap = Arc::new(DashMap::new());
+        let metrics = Arc::new(NotifyMetrics::default());
+        let s1 = subscribe(&users, &presence, &metrics, 1);
+        let s2 = subscribe(&users, &presence, &metrics, 1);
+        assert_eq!(users.get(&1).unwrap().receiver_count(), 2);
+
+        // 仍有其他连接时保留
+        drop(s1);
+        assert!(users.contains_key(&1));
+        assert!(presence.get(&1).unwrap().online);
+
+        drop(s2);
+        assert!(!users.contains_key(&1));
+        assert!(!presence.get(&1).unwrap().online);
+    }
+}